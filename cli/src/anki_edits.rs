@@ -0,0 +1,149 @@
+//! Fields which were edited directly in anki since anki-typst wrote them last.
+//!
+//! The last written values come from the [`crate::journal`].
+//! A field counts as edited in anki if its current value differs from the last written one,
+//! it only conflicts with typst if the new value from typst differs as well.
+
+use std::collections::HashMap;
+use std::io::{BufRead, IsTerminal, Write};
+
+use color_eyre::Result;
+use tracing::{info, warn};
+
+use crate::api;
+use crate::config::AnkiEditPolicy;
+use crate::journal::Snapshot;
+use crate::metadata::Note;
+
+/// A field whose value in anki and typst changed since it was written last.
+struct Conflict<'a> {
+    note: &'a Note,
+    note_id: usize,
+    field: &'a str,
+    typst: &'a str,
+    last_written: &'a str,
+    anki: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Anki,
+    Typst,
+}
+
+/// Decide for each conflicting field of `notes` which value is written, by `policy`.
+///
+/// `notes` are the anki id, the note from typst and the note which is about to be written.
+/// If the value of anki is kept, it replaces the value of typst in the written note.
+/// With `dry_run` nobody is asked, the value of anki is kept instead.
+pub fn resolve(
+    policy: AnkiEditPolicy,
+    last_written: &HashMap<usize, Snapshot>,
+    current: &HashMap<usize, Snapshot>,
+    notes: &mut [(usize, Note, api::Note)],
+    dry_run: bool,
+) -> Result<()> {
+    let interactive = std::io::stdin().is_terminal();
+    for (note_id, note, api_note) in notes.iter_mut() {
+        let (Some(last), Some(current)) = (last_written.get(note_id), current.get(note_id)) else {
+            continue;
+        };
+        let mut keep = Vec::new();
+        for (field, typst) in &api_note.fields {
+            let (Some(last_written), Some(anki)) =
+                (last.fields.get(field), current.fields.get(field))
+            else {
+                continue;
+            };
+            if anki == last_written || anki == typst {
+                continue;
+            }
+            let conflict = Conflict {
+                note,
+                note_id: *note_id,
+                field,
+                typst,
+                last_written,
+                anki,
+            };
+            let choice = match policy {
+                AnkiEditPolicy::Keep => {
+                    warn!(
+                        "field {} of note {} was edited in anki, keeping it",
+                        field,
+                        conflict.name()
+                    );
+                    Choice::Anki
+                }
+                AnkiEditPolicy::Overwrite => {
+                    info!(
+                        "field {} of note {} was edited in anki, overwriting it",
+                        field,
+                        conflict.name()
+                    );
+                    Choice::Typst
+                }
+                AnkiEditPolicy::Prompt if dry_run || !interactive => {
+                    warn!(
+                        "field {} of note {} was edited in anki, keeping it since nobody can be asked",
+                        field,
+                        conflict.name()
+                    );
+                    Choice::Anki
+                }
+                AnkiEditPolicy::Prompt => conflict.prompt()?,
+            };
+            if choice == Choice::Anki {
+                keep.push((field.clone(), anki.clone()));
+            }
+        }
+        api_note.fields.extend(keep);
+    }
+
+    Ok(())
+}
+
+impl Conflict<'_> {
+    fn name(&self) -> String {
+        match &self.note.id {
+            Some(id) => format!("{id} ({})", self.note_id),
+            None => self.note_id.to_string(),
+        }
+    }
+
+    /// Show the three values and ask which one is written.
+    ///
+    /// The value of anki is kept if stdin is closed.
+    fn prompt(&self) -> Result<Choice> {
+        let mut stderr = std::io::stderr().lock();
+        writeln!(
+            stderr,
+            "\nfield `{}` of note {} in deck {} was edited in anki",
+            self.field,
+            self.name(),
+            self.note.deck
+        )?;
+        for (label, value) in [
+            ("typst", self.typst),
+            ("last synced", self.last_written),
+            ("anki", self.anki),
+        ] {
+            writeln!(stderr, "--- {label}\n{}", value.trim())?;
+        }
+
+        let stdin = std::io::stdin();
+        loop {
+            write!(stderr, "keep [a]nki's value or write [t]ypst's value? ")?;
+            stderr.flush()?;
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(Choice::Anki);
+            }
+            match line.trim() {
+                "a" | "anki" => return Ok(Choice::Anki),
+                "t" | "typst" => return Ok(Choice::Typst),
+                _ => {}
+            }
+        }
+    }
+}
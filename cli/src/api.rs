@@ -1,34 +1,183 @@
 mod types;
 
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
 use std::{borrow::Cow, collections::HashMap};
 
-use color_eyre::{Help, Result};
+use color_eyre::{Help, Report, Result};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, info, warn};
 
 use types::{empty, Request};
 
+/// Options for the connection to AnkiConnect.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ClientOptions {
+    /// Address of AnkiConnect.
+    pub endpoint: String,
+    /// Timeout for a single request in seconds.
+    pub timeout: u64,
+    /// How often a request is retried if Anki can't be reached.
+    pub retries: u32,
+    /// Delay before the first retry in milliseconds. The delay doubles with each retry.
+    pub retry_delay: u64,
+    /// Interval in milliseconds to check whether Anki is available when waiting for it.
+    pub poll_interval: u64,
+    /// Key which is sent with each request, if AnkiConnect requires one.
+    pub api_key: Option<String>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from("http://localhost:8765"),
+            timeout: 30,
+            retries: 3,
+            retry_delay: 500,
+            poll_interval: 2000,
+            api_key: None,
+        }
+    }
+}
+
+/// Client for AnkiConnect.
+#[derive(Debug, Clone)]
+pub struct AnkiConnect {
+    agent: ureq::Agent,
+    options: ClientOptions,
+}
+
+/// Anki couldn't be reached, even after retrying.
+#[derive(Debug)]
+pub struct Unreachable {
+    endpoint: String,
+    source: Box<ureq::Error>,
+}
+
+impl Display for Unreachable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "can't reach anki at {}", self.endpoint)
+    }
+}
+
+impl std::error::Error for Unreachable {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Whether the error was caused by Anki not being reachable.
+pub fn is_unreachable(e: &Report) -> bool {
+    e.downcast_ref::<Unreachable>().is_some()
+}
+
+impl AnkiConnect {
+    pub fn new(options: ClientOptions) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(options.timeout))
+            .build();
+        Self { agent, options }
+    }
+
+    /// Block until Anki answers requests.
+    pub fn wait_until_available(&self) -> Result<()> {
+        // polling is the retry, so don't retry each request
+        let probe = Self {
+            agent: self.agent.clone(),
+            options: ClientOptions {
+                retries: 0,
+                ..self.options.clone()
+            },
+        };
+        let mut logged = false;
+        loop {
+            match version(&probe) {
+                Ok(version) => {
+                    if logged {
+                        info!("anki is available again (AnkiConnect version {})", version);
+                    }
+                    return Ok(());
+                }
+                Err(e) if is_unreachable(&e) => {
+                    if !logged {
+                        info!("waiting for anki at {}", self.options.endpoint);
+                        logged = true;
+                    }
+                    debug!("anki is not available yet: {}", e);
+                    std::thread::sleep(Duration::from_millis(self.options.poll_interval));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Send `request`, retrying it if anki can't be reached.
+    ///
+    /// Only requests which never reached anki are retried,
+    /// others (e.g. after a read timeout) may already have been applied and aren't safe to repeat.
+    fn send(&self, request: &impl Serialize) -> Result<ureq::Response> {
+        let mut delay = Duration::from_millis(self.options.retry_delay);
+        let mut attempt = 0;
+        loop {
+            match self.agent.post(&self.options.endpoint).send_json(request) {
+                Ok(v) => return Ok(v),
+                Err(ureq::Error::Transport(t))
+                    if is_connection_error(&t) && attempt < self.options.retries =>
+                {
+                    attempt += 1;
+                    warn!(
+                        "request to anki failed (attempt {}/{}), retrying in {:?}: {}",
+                        attempt,
+                        self.options.retries + 1,
+                        delay,
+                        t
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(ureq::Error::Transport(t)) if is_connection_error(&t) => {
+                    return Err(Report::new(Unreachable {
+                        endpoint: self.options.endpoint.clone(),
+                        source: Box::new(ureq::Error::Transport(t)),
+                    }))
+                    .note("is anki open?")
+                    .note("you also need to install anki-connect: https://ankiweb.net/shared/info/2055492159")
+                    .suggestion("use `--wait-for-anki` to wait until anki is available");
+                }
+                Err(e @ ureq::Error::Transport(_)) => {
+                    return Err(Report::new(e))
+                        .note("the request reached anki, but its answer couldn't be read")
+                        .suggestion(format!(
+                            "increase `timeout` in the `anki` section of the config if anki needs more than {} seconds to answer",
+                            self.options.timeout
+                        ));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Whether the request failed before it reached anki, so it is safe to repeat.
+fn is_connection_error(transport: &ureq::Transport) -> bool {
+    matches!(
+        transport.kind(),
+        ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Dns
+    )
+}
+
 pub fn request<'a, T: Serialize + 'a, U: for<'de> Deserialize<'de> + std::fmt::Debug>(
+    client: &AnkiConnect,
     action: impl Into<Cow<'a, str>>,
     data: &'a T,
 ) -> Result<U> {
     let action = action.into();
 
     debug!("requesting action {}", action);
-    let request = Request::new(action.clone(), data);
-    let res = match ureq::post("http://localhost:8765").send_json(&request) {
-        Ok(v) => v,
-        Err(e) => {
-            if let ureq::Error::Transport(ref t) = e {
-                if t.kind() == ureq::ErrorKind::ConnectionFailed {
-                    return Err(e)
-                        .note("is anki open?")
-                        .note("you also need to install anki-connect: https://ankiweb.net/shared/info/2055492159");
-                }
-            }
-            return Err(e.into());
-        }
-    };
+    let request = Request::new(action.clone(), data, client.options.api_key.as_deref());
+    let res = client.send(&request)?;
 
     debug!("got response with status {}", res.status());
     let raw = res.into_string()?;
@@ -40,6 +189,7 @@ pub fn request<'a, T: Serialize + 'a, U: for<'de> Deserialize<'de> + std::fmt::D
 }
 
 pub fn request_multi<'a, T: Serialize + 'a, U: for<'de> Deserialize<'de> + std::fmt::Debug>(
+    client: &AnkiConnect,
     action: &str,
     data: impl IntoIterator<Item = T>,
 ) -> Result<Vec<U>> {
@@ -57,6 +207,7 @@ pub fn request_multi<'a, T: Serialize + 'a, U: for<'de> Deserialize<'de> + std::
     }
 
     let res = request::<_, Vec<types::ReqResult<U>>>(
+        client,
         "multi",
         &Params {
             actions: data
@@ -71,28 +222,127 @@ pub fn request_multi<'a, T: Serialize + 'a, U: for<'de> Deserialize<'de> + std::
 /// Returns
 /// - `id` if the note was created
 /// - `None` if the note wasn't created (e.g. duplicate)
-pub fn create_deck(deck: &str) -> Result<Option<usize>> {
+pub fn create_deck(client: &AnkiConnect, deck: &str) -> Result<Option<usize>> {
     #[derive(Debug, Serialize)]
     #[serde(rename_all = "camelCase")]
     struct Params<'a> {
         deck: &'a str,
     }
 
-    request("createDeck", &Params { deck })
+    request(client, "createDeck", &Params { deck })
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DeckNames(pub Vec<String>);
 
-pub fn get_deck_names() -> Result<DeckNames> {
-    request("deckNames", &empty())
+pub fn get_deck_names(client: &AnkiConnect) -> Result<DeckNames> {
+    request(client, "deckNames", &empty())
+}
+
+/// Id of anki's `Default` options group, which all decks use initially.
+pub const DEFAULT_DECK_CONFIG: usize = 1;
+
+/// An options group of decks, as anki stores it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeckConfig {
+    pub id: usize,
+    pub name: String,
+    /// All other options
+    #[serde(flatten)]
+    pub options: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Result of actions which return `false` instead of an error.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OrFalse<T> {
+    Value(T),
+    False(#[allow(unused)] bool),
+}
+
+impl<T> OrFalse<T> {
+    fn into_option(self) -> Option<T> {
+        match self {
+            Self::Value(value) => Some(value),
+            Self::False(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeckConfigParams<'a> {
+    deck: &'a str,
+}
+
+/// Options group of `deck`, `None` if the deck doesn't exist.
+pub fn get_deck_config(client: &AnkiConnect, deck: &str) -> Result<Option<DeckConfig>> {
+    request(client, "getDeckConfig", &DeckConfigParams { deck }).map(OrFalse::into_option)
+}
+
+/// Options group of each of the decks, in the same order.
+pub fn get_deck_config_multi<'a>(
+    client: &AnkiConnect,
+    decks: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<Option<DeckConfig>>> {
+    let configs: Vec<OrFalse<DeckConfig>> = request_multi(
+        client,
+        "getDeckConfig",
+        decks.into_iter().map(|deck| DeckConfigParams { deck }),
+    )?;
+    Ok(configs.into_iter().map(OrFalse::into_option).collect())
+}
+
+/// Returns whether the options group was saved, it must exist already.
+pub fn save_deck_config(client: &AnkiConnect, config: &DeckConfig) -> Result<bool> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        config: &'a DeckConfig,
+    }
+
+    request(client, "saveDeckConfig", &Params { config })
+}
+
+/// Returns whether the options group exists and was set for all `decks`.
+pub fn set_deck_config_id(
+    client: &AnkiConnect,
+    decks: &[String],
+    config_id: usize,
+) -> Result<bool> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        decks: &'a [String],
+        config_id: usize,
+    }
+
+    request(client, "setDeckConfigId", &Params { decks, config_id })
+}
+
+/// Create a copy of the options group `clone_from` with `name`.
+///
+/// Returns the id of the new options group, `None` if `clone_from` doesn't exist.
+pub fn clone_deck_config_id(
+    client: &AnkiConnect,
+    name: &str,
+    clone_from: usize,
+) -> Result<Option<usize>> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        name: &'a str,
+        clone_from: usize,
+    }
+
+    request(client, "cloneDeckConfigId", &Params { name, clone_from }).map(OrFalse::into_option)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ModelNames(pub Vec<String>);
 
-pub fn get_model_names() -> Result<ModelNames> {
-    request("modelNames", &empty())
+pub fn get_model_names(client: &AnkiConnect) -> Result<ModelNames> {
+    request(client, "modelNames", &empty())
 }
 
 #[derive(Debug, Deserialize)]
@@ -159,7 +409,7 @@ pub struct ModelCreation {
     // ]
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateModelData {
     pub model_name: String,
@@ -169,8 +419,8 @@ pub struct CreateModelData {
     pub card_templates: Vec<HashMap<String, String>>,
 }
 
-pub fn create_model(data: &CreateModelData) -> Result<ModelCreation> {
-    request("createModel", data)
+pub fn create_model(client: &AnkiConnect, data: &CreateModelData) -> Result<ModelCreation> {
+    request(client, "createModel", data)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -188,6 +438,13 @@ impl<T> Default for SingleOrMulti<T> {
 }
 
 impl<T> SingleOrMulti<T> {
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            Self::Multi(v) => v,
+            Self::Single(v) => std::slice::from_ref(v),
+        }
+    }
+
     #[allow(unused)]
     pub fn into_vec(self) -> Vec<T> {
         match self {
@@ -206,6 +463,9 @@ pub struct MediaData {
     pub(crate) inner: MediaDataInner,
     #[serde(default = "MediaData::default_delete_existing")]
     pub(crate) delete_existing: bool,
+    /// Fields the file is appended to when it is attached to a note
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) fields: Vec<String>,
 }
 
 impl MediaData {
@@ -214,7 +474,7 @@ impl MediaData {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum MediaDataInner {
     Data(String),
@@ -223,11 +483,22 @@ pub enum MediaDataInner {
 }
 
 /// returns the assigned filename
-pub fn store_media_file(data: &MediaData) -> Result<String> {
-    request("storeMediaFile", data)
+pub fn store_media_file(client: &AnkiConnect, data: &MediaData) -> Result<String> {
+    request(client, "storeMediaFile", data)
+}
+
+/// Base64 encoded content of the media file, `None` if it doesn't exist.
+pub fn retrieve_media_file(client: &AnkiConnect, filename: &str) -> Result<Option<String>> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        filename: &'a str,
+    }
+
+    request(client, "retrieveMediaFile", &Params { filename }).map(OrFalse::into_option)
 }
 
-#[derive(Default, Debug, Serialize)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Note {
     pub deck_name: String,
@@ -244,14 +515,14 @@ pub struct Note {
     // options
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct NoteInfoField {
     pub value: String,
     pub order: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NoteInfo {
     pub note_id: usize,
@@ -261,21 +532,59 @@ pub struct NoteInfo {
     pub cards: Vec<usize>,
 }
 
-pub fn add_notes(notes: &[Note]) -> Result<Vec<Option<usize>>> {
+pub fn add_notes(client: &AnkiConnect, notes: &[Note]) -> Result<Vec<Option<usize>>> {
     #[derive(Debug, Serialize)]
     #[serde(rename_all = "camelCase")]
     struct NoteParams<'a> {
         notes: &'a [Note],
     }
 
-    request("addNotes", &NoteParams { notes })
+    request(client, "addNotes", &NoteParams { notes })
+}
+
+/// Whether each of the notes could be added, i.e. its deck and model exist and it is no duplicate.
+pub fn can_add_notes(client: &AnkiConnect, notes: &[Note]) -> Result<Vec<bool>> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct NoteParams<'a> {
+        notes: &'a [Note],
+    }
+
+    request(client, "canAddNotes", &NoteParams { notes })
+}
+
+/// Update the fields and tags of the note with `id`.
+///
+/// Deck and model of `note` are ignored.
+pub fn update_note(client: &AnkiConnect, id: usize, note: &Note) -> Result<()> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct NoteParams<'a> {
+        note: UpdateNote<'a>,
+    }
+
+    request(
+        client,
+        "updateNote",
+        &NoteParams {
+            note: UpdateNote { id, note },
+        },
+    )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateNote<'a> {
+    pub id: usize,
+    #[serde(flatten)]
+    pub note: &'a Note,
 }
 
 /// Returns
 /// - `id` if the note was created
 /// - `None` if the note wasn't created (e.g. duplicate)
 #[allow(unused)]
-pub fn add_note(note: &Note) -> Result<Option<usize>> {
+pub fn add_note(client: &AnkiConnect, note: &Note) -> Result<Option<usize>> {
     #[derive(Debug, Deserialize)]
     pub struct AddNote(Option<usize>);
 
@@ -285,7 +594,7 @@ pub fn add_note(note: &Note) -> Result<Option<usize>> {
         note: &'a Note,
     }
 
-    let res = request::<_, AddNote>("addNote", &NoteParams { note });
+    let res = request::<_, AddNote>(client, "addNote", &NoteParams { note });
     match res {
         Err(e)
             if e.root_cause()
@@ -308,14 +617,20 @@ struct ModelFieldNameParams<'a> {
 }
 
 #[allow(unused)]
-pub fn get_model_field_names(model_name: &str) -> Result<ModelFieldNames> {
-    request("modelFieldNames", &ModelFieldNameParams { model_name })
+pub fn get_model_field_names(client: &AnkiConnect, model_name: &str) -> Result<ModelFieldNames> {
+    request(
+        client,
+        "modelFieldNames",
+        &ModelFieldNameParams { model_name },
+    )
 }
 
 pub fn get_model_field_names_multi<'a>(
+    client: &AnkiConnect,
     model_names: impl IntoIterator<Item = impl Into<&'a str>>,
 ) -> Result<Vec<ModelFieldNames>> {
     request_multi(
+        client,
         "modelFieldNames",
         model_names
             .into_iter()
@@ -325,28 +640,85 @@ pub fn get_model_field_names_multi<'a>(
     )
 }
 
+/// Front and back template of a card type.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CardTemplate {
+    #[serde(rename = "Front")]
+    pub front: String,
+    #[serde(rename = "Back")]
+    pub back: String,
+}
+
+/// Card templates of a model by name, in the order of the model.
+pub fn model_templates(
+    client: &AnkiConnect,
+    model_name: &str,
+) -> Result<IndexMap<String, CardTemplate>> {
+    request(
+        client,
+        "modelTemplates",
+        &ModelFieldNameParams { model_name },
+    )
+}
+
+pub fn model_styling(client: &AnkiConnect, model_name: &str) -> Result<String> {
+    #[derive(Debug, Deserialize)]
+    struct Styling {
+        css: String,
+    }
+
+    request(client, "modelStyling", &ModelFieldNameParams { model_name })
+        .map(|styling: Styling| styling.css)
+}
+
+/// Add the field `field_name` at `index` to the model, existing notes get an empty value.
+pub fn model_field_add(
+    client: &AnkiConnect,
+    model_name: &str,
+    field_name: &str,
+    index: usize,
+) -> Result<()> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        model_name: &'a str,
+        field_name: &'a str,
+        index: usize,
+    }
+
+    request(
+        client,
+        "modelFieldAdd",
+        &Params {
+            model_name,
+            field_name,
+            index,
+        },
+    )
+}
+
 /// See <https://docs.ankiweb.net/searching.html>
-pub fn find_notes(query: &str) -> Result<Vec<usize>> {
+pub fn find_notes(client: &AnkiConnect, query: &str) -> Result<Vec<usize>> {
     #[derive(Debug, Serialize)]
     #[serde(rename_all = "camelCase")]
     struct Params<'a> {
         query: &'a str,
     }
 
-    request("findNotes", &Params { query })
+    request(client, "findNotes", &Params { query })
 }
 
-pub fn notes_info(ids: &[usize]) -> Result<Vec<NoteInfo>> {
+pub fn notes_info(client: &AnkiConnect, ids: &[usize]) -> Result<Vec<NoteInfo>> {
     #[derive(Debug, Serialize)]
     #[serde(rename_all = "camelCase")]
     struct Params<'a> {
         notes: &'a [usize],
     }
 
-    request("notesInfo", &Params { notes: ids })
+    request(client, "notesInfo", &Params { notes: ids })
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CardInfo {
     pub answer: String,
@@ -369,16 +741,91 @@ pub struct CardInfo {
     pub r#mod: i32,
 }
 
-pub fn cards_info(ids: &[usize]) -> Result<Vec<CardInfo>> {
+pub fn cards_info(client: &AnkiConnect, ids: &[usize]) -> Result<Vec<CardInfo>> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        cards: &'a [usize],
+    }
+
+    request(client, "cardsInfo", &Params { cards: ids })
+}
+
+/// Returns whether any of the cards wasn't suspended already.
+pub fn suspend(client: &AnkiConnect, cards: &[usize]) -> Result<bool> {
     #[derive(Debug, Serialize)]
     #[serde(rename_all = "camelCase")]
     struct Params<'a> {
         cards: &'a [usize],
     }
 
-    request("cardsInfo", &Params { cards: ids })
+    request(client, "suspend", &Params { cards })
+}
+
+/// Returns whether any of the cards was suspended.
+pub fn unsuspend(client: &AnkiConnect, cards: &[usize]) -> Result<bool> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        cards: &'a [usize],
+    }
+
+    request(client, "unsuspend", &Params { cards })
+}
+
+/// Whether each card is suspended, `None` if it doesn't exist.
+pub fn are_suspended(client: &AnkiConnect, cards: &[usize]) -> Result<Vec<Option<bool>>> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        cards: &'a [usize],
+    }
+
+    request(client, "areSuspended", &Params { cards })
+}
+
+/// Reset the cards to new cards.
+pub fn forget_cards(client: &AnkiConnect, cards: &[usize]) -> Result<()> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        cards: &'a [usize],
+    }
+
+    request(client, "forgetCards", &Params { cards })
+}
+
+/// Put the cards into relearning.
+pub fn relearn_cards(client: &AnkiConnect, cards: &[usize]) -> Result<()> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        cards: &'a [usize],
+    }
+
+    request(client, "relearnCards", &Params { cards })
+}
+
+/// Name of the profile which is currently open in anki.
+pub fn get_active_profile(client: &AnkiConnect) -> Result<String> {
+    request(client, "getActiveProfile", &empty())
+}
+
+/// Open the profile `name`, returns whether it was opened.
+pub fn load_profile(client: &AnkiConnect, name: &str) -> Result<bool> {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params<'a> {
+        name: &'a str,
+    }
+
+    request(client, "loadProfile", &Params { name })
+}
+
+pub fn sync(client: &AnkiConnect) -> Result<()> {
+    request(client, "sync", &empty())
 }
 
-pub fn sync() -> Result<()> {
-    request("sync", &empty())
+pub fn version(client: &AnkiConnect) -> Result<u32> {
+    request(client, "version", &empty())
 }
@@ -1,6 +1,7 @@
 use color_eyre::{eyre::eyre, Help, Result};
 use std::borrow::Cow;
 
+use serde::de::value::UnitDeserializer;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize)]
@@ -8,14 +9,17 @@ pub struct Request<'a, T> {
     action: Cow<'a, str>,
     params: &'a T,
     version: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a str>,
 }
 
 impl<'a, T: Serialize> Request<'a, T> {
-    pub fn new(action: impl Into<Cow<'a, str>>, params: &'a T) -> Self {
+    pub fn new(action: impl Into<Cow<'a, str>>, params: &'a T, key: Option<&'a str>) -> Self {
         Self {
             action: action.into(),
             params,
             version: 6,
+            key,
         }
     }
 }
@@ -31,7 +35,7 @@ pub enum ReqResult<T> {
     Struct(ReqResultStruct<T>),
 }
 
-impl<T: std::fmt::Debug> ReqResult<T> {
+impl<T: std::fmt::Debug + for<'de> Deserialize<'de>> ReqResult<T> {
     pub fn get(self) -> Result<T> {
         match self {
             Self::Raw(t) => Ok(t),
@@ -46,10 +50,12 @@ pub struct ReqResultStruct<T> {
     error: Option<String>,
 }
 
-impl<T: std::fmt::Debug> ReqResultStruct<T> {
+impl<T: std::fmt::Debug + for<'de> Deserialize<'de>> ReqResultStruct<T> {
     pub fn get(self) -> Result<T> {
         match (self.result, self.error) {
-            (None, None) => Err(eyre!("invalid response, got neither result or error")),
+            // actions without result (e.g. `sync`) return `null`
+            (None, None) => T::deserialize(UnitDeserializer::<serde::de::value::Error>::new())
+                .map_err(|_| eyre!("invalid response, got neither result or error")),
             (None, Some(error)) => Err(eyre!("anki returned an error: {}", error)),
             (Some(result), None) => Ok(result),
             (Some(result), Some(error)) => Err(eyre!("invalid response, got result and error")
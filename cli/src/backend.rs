@@ -0,0 +1,246 @@
+mod memory;
+mod overlay;
+pub mod search;
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use indexmap::IndexMap;
+
+use crate::api::{
+    self, AnkiConnect, CardInfo, CardTemplate, CreateModelData, DeckConfig, MediaData, Note,
+    NoteInfo,
+};
+
+pub use memory::InMemory;
+pub use overlay::Overlay;
+
+/// Storage for decks, models, notes, cards and media.
+///
+/// The method names follow the AnkiConnect actions, see <https://foosoft.net/projects/anki-connect/>.
+pub trait Backend {
+    fn deck_names(&self) -> Result<Vec<String>>;
+
+    /// Returns
+    /// - `id` if the deck was created
+    /// - `None` if the deck wasn't created
+    fn create_deck(&self, deck: &str) -> Result<Option<usize>>;
+
+    /// Options group of `deck`, as anki stores it.
+    fn get_deck_config(&self, deck: &str) -> Result<DeckConfig>;
+
+    /// Options group of each of the decks, in the same order.
+    fn get_deck_configs(&self, decks: &[String]) -> Result<Vec<DeckConfig>> {
+        decks
+            .iter()
+            .map(|deck| self.get_deck_config(deck))
+            .collect()
+    }
+
+    /// Save an existing options group, it is identified by its `id`.
+    fn save_deck_config(&self, config: &DeckConfig) -> Result<()>;
+
+    /// Use the options group with `config_id` for all `decks`.
+    fn set_deck_config_id(&self, decks: &[String], config_id: usize) -> Result<()>;
+
+    /// Create a copy of the options group `clone_from` with `name`, returns the new id.
+    fn clone_deck_config_id(&self, name: &str, clone_from: usize) -> Result<usize>;
+
+    fn model_names(&self) -> Result<Vec<String>>;
+
+    /// Get the field names for each of the models, in the same order.
+    fn model_field_names(&self, model_names: &[String]) -> Result<Vec<Vec<String>>>;
+
+    fn create_model(&self, data: &CreateModelData) -> Result<()>;
+
+    /// Card templates of the model by name, in the order of the model.
+    fn model_templates(&self, model_name: &str) -> Result<IndexMap<String, CardTemplate>>;
+
+    /// Css which is shared by all cards of the model.
+    fn model_styling(&self, model_name: &str) -> Result<String>;
+
+    /// Add the field `field_name` at `index` to the model, existing notes get an empty value.
+    fn model_field_add(&self, model_name: &str, field_name: &str, index: usize) -> Result<()>;
+
+    /// Returns for each note
+    /// - `id` if the note was created
+    /// - `None` if the note wasn't created (e.g. duplicate)
+    fn add_notes(&self, notes: &[Note]) -> Result<Vec<Option<usize>>>;
+
+    /// Whether each of the notes could be added, i.e. its deck and model exist and it is no duplicate.
+    fn can_add_notes(&self, notes: &[Note]) -> Result<Vec<bool>>;
+
+    /// Update the fields and tags of the note with `id`.
+    fn update_note(&self, id: usize, note: &Note) -> Result<()>;
+
+    /// See <https://docs.ankiweb.net/searching.html>
+    fn find_notes(&self, query: &str) -> Result<Vec<usize>>;
+
+    fn notes_info(&self, ids: &[usize]) -> Result<Vec<NoteInfo>>;
+
+    fn cards_info(&self, ids: &[usize]) -> Result<Vec<CardInfo>>;
+
+    fn suspend(&self, cards: &[usize]) -> Result<()>;
+
+    fn unsuspend(&self, cards: &[usize]) -> Result<()>;
+
+    /// Whether each card is suspended, `None` if it doesn't exist.
+    fn are_suspended(&self, cards: &[usize]) -> Result<Vec<Option<bool>>>;
+
+    /// Reset the cards to new cards, their review history is kept.
+    fn forget_cards(&self, cards: &[usize]) -> Result<()>;
+
+    /// Put the cards into relearning.
+    fn relearn_cards(&self, cards: &[usize]) -> Result<()>;
+
+    /// Returns the assigned filename
+    fn store_media_file(&self, data: &MediaData) -> Result<String>;
+
+    /// Base64 encoded content of the media file, `None` if it doesn't exist.
+    fn retrieve_media_file(&self, filename: &str) -> Result<Option<String>>;
+
+    /// Name of the profile whose collection is used.
+    fn active_profile(&self) -> Result<String>;
+
+    /// Switch to the profile `name`.
+    fn load_profile(&self, name: &str) -> Result<()>;
+
+    /// Sync the collection to ankiweb
+    fn sync(&self) -> Result<()>;
+}
+
+impl Backend for AnkiConnect {
+    fn deck_names(&self) -> Result<Vec<String>> {
+        api::get_deck_names(self).map(|names| names.0)
+    }
+
+    fn create_deck(&self, deck: &str) -> Result<Option<usize>> {
+        api::create_deck(self, deck)
+    }
+
+    fn get_deck_config(&self, deck: &str) -> Result<DeckConfig> {
+        api::get_deck_config(self, deck)?.ok_or_else(|| eyre!("deck was not found: {}", deck))
+    }
+
+    fn get_deck_configs(&self, decks: &[String]) -> Result<Vec<DeckConfig>> {
+        api::get_deck_config_multi(self, decks.iter().map(String::as_str))?
+            .into_iter()
+            .zip(decks)
+            .map(|(config, deck)| config.ok_or_else(|| eyre!("deck was not found: {}", deck)))
+            .collect()
+    }
+
+    fn save_deck_config(&self, config: &DeckConfig) -> Result<()> {
+        if !api::save_deck_config(self, config)? {
+            bail!("options group {} could not be saved", config.name);
+        }
+        Ok(())
+    }
+
+    fn set_deck_config_id(&self, decks: &[String], config_id: usize) -> Result<()> {
+        if !api::set_deck_config_id(self, decks, config_id)? {
+            bail!(
+                "options group {} could not be set for decks {}",
+                config_id,
+                decks.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    fn clone_deck_config_id(&self, name: &str, clone_from: usize) -> Result<usize> {
+        api::clone_deck_config_id(self, name, clone_from)?
+            .ok_or_else(|| eyre!("options group {} could not be cloned", clone_from))
+    }
+
+    fn model_names(&self) -> Result<Vec<String>> {
+        api::get_model_names(self).map(|names| names.0)
+    }
+
+    fn model_field_names(&self, model_names: &[String]) -> Result<Vec<Vec<String>>> {
+        api::get_model_field_names_multi(self, model_names.iter().map(String::as_str))
+            .map(|names| names.into_iter().map(|names| names.0).collect())
+    }
+
+    fn create_model(&self, data: &CreateModelData) -> Result<()> {
+        api::create_model(self, data).map(|_| ())
+    }
+
+    fn model_templates(&self, model_name: &str) -> Result<IndexMap<String, CardTemplate>> {
+        api::model_templates(self, model_name)
+    }
+
+    fn model_styling(&self, model_name: &str) -> Result<String> {
+        api::model_styling(self, model_name)
+    }
+
+    fn model_field_add(&self, model_name: &str, field_name: &str, index: usize) -> Result<()> {
+        api::model_field_add(self, model_name, field_name, index)
+    }
+
+    fn add_notes(&self, notes: &[Note]) -> Result<Vec<Option<usize>>> {
+        api::add_notes(self, notes)
+    }
+
+    fn can_add_notes(&self, notes: &[Note]) -> Result<Vec<bool>> {
+        api::can_add_notes(self, notes)
+    }
+
+    fn update_note(&self, id: usize, note: &Note) -> Result<()> {
+        api::update_note(self, id, note)
+    }
+
+    fn find_notes(&self, query: &str) -> Result<Vec<usize>> {
+        api::find_notes(self, query)
+    }
+
+    fn notes_info(&self, ids: &[usize]) -> Result<Vec<NoteInfo>> {
+        api::notes_info(self, ids)
+    }
+
+    fn cards_info(&self, ids: &[usize]) -> Result<Vec<CardInfo>> {
+        api::cards_info(self, ids)
+    }
+
+    fn suspend(&self, cards: &[usize]) -> Result<()> {
+        api::suspend(self, cards).map(|_| ())
+    }
+
+    fn unsuspend(&self, cards: &[usize]) -> Result<()> {
+        api::unsuspend(self, cards).map(|_| ())
+    }
+
+    fn are_suspended(&self, cards: &[usize]) -> Result<Vec<Option<bool>>> {
+        api::are_suspended(self, cards)
+    }
+
+    fn forget_cards(&self, cards: &[usize]) -> Result<()> {
+        api::forget_cards(self, cards)
+    }
+
+    fn relearn_cards(&self, cards: &[usize]) -> Result<()> {
+        api::relearn_cards(self, cards)
+    }
+
+    fn store_media_file(&self, data: &MediaData) -> Result<String> {
+        api::store_media_file(self, data)
+    }
+
+    fn retrieve_media_file(&self, filename: &str) -> Result<Option<String>> {
+        api::retrieve_media_file(self, filename)
+    }
+
+    fn active_profile(&self) -> Result<String> {
+        api::get_active_profile(self)
+    }
+
+    fn load_profile(&self, name: &str) -> Result<()> {
+        if !api::load_profile(self, name)? {
+            bail!("profile {} could not be loaded", name);
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        api::sync(self)
+    }
+}
@@ -0,0 +1,1138 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::{Help, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::api::{
+    CardInfo, CardTemplate, CreateModelData, DeckConfig, MediaData, MediaDataInner, Note, NoteInfo,
+    NoteInfoField, DEFAULT_DECK_CONFIG,
+};
+use crate::backend::search::{self, glob_match, Searchable};
+use crate::backend::Backend;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Model {
+    fields: Vec<String>,
+    css: String,
+    /// Pairs of front and back templates
+    templates: Vec<(String, String)>,
+    /// Names of `templates`, anki's default names are used for missing ones
+    #[serde(default)]
+    template_names: Vec<String>,
+}
+
+impl Model {
+    fn template_name(&self, ord: usize) -> String {
+        self.template_names
+            .get(ord)
+            .cloned()
+            .unwrap_or_else(|| format!("Card {}", ord + 1))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredNote {
+    model: String,
+    fields: IndexMap<String, String>,
+    tags: Vec<String>,
+    cards: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Card {
+    note: usize,
+    deck: String,
+    ord: usize,
+    #[serde(default)]
+    review: Review,
+}
+
+const QUEUE_SUSPENDED: i32 = -1;
+const QUEUE_NEW: i32 = 0;
+const QUEUE_LEARNING: i32 = 1;
+const QUEUE_REVIEW: i32 = 2;
+
+/// Scheduling information of a card, new cards have the default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Review {
+    interval: i32,
+    queue: i32,
+    due: Option<i32>,
+    reps: i32,
+    lapses: i32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Collection {
+    decks: BTreeMap<String, usize>,
+    models: BTreeMap<String, Model>,
+    notes: BTreeMap<usize, StoredNote>,
+    cards: BTreeMap<usize, Card>,
+    media: BTreeMap<String, MediaDataInner>,
+    /// Options groups by id, see [`Collection::deck_configs_mut`]
+    #[serde(default)]
+    deck_configs: BTreeMap<usize, DeckConfig>,
+    /// Options group of decks which don't use the default one
+    #[serde(default)]
+    deck_config_ids: BTreeMap<String, usize>,
+    /// Known profiles, the first one is active, see [`Collection::profiles`]
+    #[serde(default)]
+    profiles: Vec<String>,
+    last_id: usize,
+}
+
+/// Name of anki's first profile.
+const DEFAULT_PROFILE: &str = "User 1";
+
+/// The most common options of anki's `Default` options group.
+fn default_deck_config() -> DeckConfig {
+    let options = json!({
+        "new": {
+            "perDay": 20,
+            "delays": [1.0, 10.0],
+            "ints": [1, 4, 0],
+        },
+        "rev": {
+            "perDay": 200,
+            "maxIvl": 36500,
+        },
+        "lapse": {
+            "delays": [10.0],
+        },
+        "desiredRetention": 0.9,
+        "fsrsWeights": [],
+        "fsrsParams5": [],
+        "fsrsParams6": [],
+    });
+    DeckConfig {
+        id: DEFAULT_DECK_CONFIG,
+        name: String::from("Default"),
+        options: match options {
+            Value::Object(options) => options,
+            _ => unreachable!(),
+        },
+    }
+}
+
+impl Collection {
+    fn next_id(&mut self) -> usize {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Options groups by id, the `Default` group always exists.
+    fn deck_configs_mut(&mut self) -> &mut BTreeMap<usize, DeckConfig> {
+        self.deck_configs
+            .entry(DEFAULT_DECK_CONFIG)
+            .or_insert_with(default_deck_config);
+        &mut self.deck_configs
+    }
+
+    /// Known profiles, the first one is active. There is at least the default profile.
+    ///
+    /// All profiles share this collection.
+    fn profiles(&mut self) -> &mut Vec<String> {
+        if self.profiles.is_empty() {
+            self.profiles.push(String::from(DEFAULT_PROFILE));
+        }
+        &mut self.profiles
+    }
+
+    /// Store a media file like anki-connect's `storeMediaFile` and return its name.
+    fn store_media(&mut self, data: &MediaData) -> Result<String> {
+        let mut filename = data.filename.clone();
+        if let (false, Some(existing)) = (data.delete_existing, self.media.get(&filename)) {
+            // urls aren't downloaded, so they are compared instead
+            let content = |media: &MediaDataInner| match media {
+                MediaDataInner::Url(url) => Ok(url.clone().into_bytes()),
+                media => media_content(media).map(Option::unwrap_or_default),
+            };
+            let new = content(&data.inner)?;
+            if new == content(existing)? {
+                return Ok(filename);
+            }
+            // like anki, a different file with the same name is stored with the hash in its name
+            // (anki uses the sha1 of the content)
+            let hash = fasthash::metro::hash64(&new);
+            filename = match filename.rsplit_once('.') {
+                Some((stem, extension)) => format!("{stem}-{hash:016x}.{extension}"),
+                None => format!("{filename}-{hash:016x}"),
+            };
+        }
+        self.media.insert(filename.clone(), data.inner.clone());
+        Ok(filename)
+    }
+
+    /// Store the media of `note` and append it to `fields`, like anki-connect does.
+    fn attach_media(&mut self, note: &Note, fields: &mut IndexMap<String, String>) -> Result<()> {
+        let media = [
+            (&note.audio, false),
+            (&note.video, false),
+            (&note.picture, true),
+        ];
+        for (media, is_picture) in media {
+            for data in media.as_slice() {
+                let filename = self.store_media(data)?;
+                for name in &data.fields {
+                    if let Some(field) = fields.get_mut(name) {
+                        if is_picture {
+                            field.push_str(&format!("<img src=\"{filename}\">"));
+                        } else {
+                            field.push_str(&format!("[sound:{filename}]"));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_duplicate(&self, model: &str, fields: &IndexMap<String, String>) -> bool {
+        let Some((_, first)) = fields.first() else {
+            return false;
+        };
+        !first.is_empty()
+            && self.notes.values().any(|note| {
+                note.model == model && note.fields.first().map(|(_, v)| v) == Some(first)
+            })
+    }
+}
+
+/// Content of a stored media file, `None` for urls which are never downloaded.
+fn media_content(media: &MediaDataInner) -> Result<Option<Vec<u8>>> {
+    match media {
+        MediaDataInner::Data(data) => Ok(Some(
+            base64::engine::general_purpose::STANDARD.decode(data)?,
+        )),
+        MediaDataInner::Path(path) => {
+            Ok(Some(std::fs::read(path).with_note(|| {
+                eyre!("while reading media file {}", path)
+            })?))
+        }
+        MediaDataInner::Url(_) => Ok(None),
+    }
+}
+
+/// A note together with its cards, used for searching.
+struct SearchNote<'a> {
+    id: usize,
+    note: &'a StoredNote,
+    cards: Vec<&'a Card>,
+}
+
+impl Searchable for SearchNote<'_> {
+    fn matches_term(&self, key: Option<&str>, value: &str) -> bool {
+        let with_children =
+            |name: &str| glob_match(value, name) || glob_match(&format!("{value}::*"), name);
+        match key {
+            None => self
+                .note
+                .fields
+                .values()
+                .any(|field| glob_match(&format!("*{value}*"), field)),
+            Some("deck") => self.cards.iter().any(|card| with_children(&card.deck)),
+            Some("tag") => self.note.tags.iter().any(|tag| with_children(tag)),
+            Some("note") => glob_match(value, &self.note.model),
+            Some("nid") => value.split(',').any(|id| id.trim().parse() == Ok(self.id)),
+            Some(field) => self.note.fields.iter().any(|(name, content)| {
+                name.eq_ignore_ascii_case(field) && glob_match(value, content)
+            }),
+        }
+    }
+}
+
+/// A [`Backend`] which keeps everything in memory.
+///
+/// It behaves like a fresh Anki collection: only the `Default` deck exists initially and
+/// notes are duplicates if their first field matches another note with the same model.
+#[derive(Debug)]
+pub struct InMemory {
+    collection: RefCell<Collection>,
+}
+
+impl Default for InMemory {
+    fn default() -> Self {
+        let mut collection = Collection::default();
+        let id = collection.next_id();
+        collection.decks.insert(String::from("Default"), id);
+
+        Self {
+            collection: RefCell::new(collection),
+        }
+    }
+}
+
+impl InMemory {
+    /// Load a collection which was stored with [`InMemory::save`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_note(|| eyre!("while reading collection from {}", path.display()))?;
+        let collection = serde_json::from_str(&content)
+            .with_note(|| eyre!("while parsing collection from {}", path.display()))?;
+        Ok(Self {
+            collection: RefCell::new(collection),
+        })
+    }
+
+    /// Store the collection as json.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(&*self.collection.borrow())?;
+        std::fs::write(path, content)
+            .with_note(|| eyre!("while writing collection to {}", path.display()))
+    }
+
+    /// Make the profiles `names` known, the first one is active.
+    pub fn set_profiles(&self, names: Vec<String>) {
+        self.collection.borrow_mut().profiles = names;
+    }
+
+    /// All stored media files by filename.
+    pub fn media(&self) -> Vec<(String, MediaDataInner)> {
+        self.collection
+            .borrow()
+            .media
+            .iter()
+            .map(|(name, data)| (name.clone(), data.clone()))
+            .collect()
+    }
+
+    /// Copy decks and models of another backend, but no notes.
+    ///
+    /// Ids start at the current time in milliseconds like in anki, so they don't collide with the
+    /// ids of `backend`, which were either created earlier by anki or counted from zero.
+    pub fn without_notes(backend: &dyn Backend) -> Result<Self> {
+        let this = Self::default();
+        {
+            let mut collection = this.collection.borrow_mut();
+            collection.last_id = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis() as usize);
+            for deck in backend.deck_names()? {
+                let id = collection.next_id();
+                collection.decks.entry(deck).or_insert(id);
+            }
+            let model_names = backend.model_names()?;
+            let field_names = backend.model_field_names(&model_names)?;
+            for (name, fields) in model_names.into_iter().zip(field_names) {
+                let templates = backend.model_templates(&name)?;
+                let css = backend.model_styling(&name)?;
+                collection.models.insert(
+                    name,
+                    Model {
+                        fields,
+                        css,
+                        template_names: templates.keys().cloned().collect(),
+                        templates: templates
+                            .into_values()
+                            .map(|template| (template.front, template.back))
+                            .collect(),
+                    },
+                );
+            }
+        }
+
+        Ok(this)
+    }
+
+    /// Store a note of another backend with its cards, keeping their ids.
+    ///
+    /// `cards` has to contain the info of all cards of the note.
+    /// Fields which only the model in this collection has are added empty.
+    pub fn insert_note(&self, note: NoteInfo, cards: Vec<CardInfo>) {
+        let mut collection = self.collection.borrow_mut();
+        for card in cards {
+            collection.last_id = collection.last_id.max(card.card_id);
+            collection.cards.insert(
+                card.card_id,
+                Card {
+                    note: card.note,
+                    deck: card.deck_name,
+                    ord: usize::try_from(card.ord).unwrap_or_default(),
+                    review: Review {
+                        interval: card.interval,
+                        queue: card.queue,
+                        due: Some(card.due),
+                        reps: card.reps,
+                        lapses: card.lapses,
+                    },
+                },
+            );
+        }
+        let mut fields = note.fields.into_iter().collect::<Vec<_>>();
+        fields.sort_by_key(|(_, field)| field.order);
+        let mut fields = fields
+            .into_iter()
+            .map(|(name, field)| (name, field.value))
+            .collect::<IndexMap<_, _>>();
+        if let Some(model) = collection.models.get(&note.model_name) {
+            for (index, name) in model.fields.iter().enumerate() {
+                if !fields.contains_key(name) {
+                    let index = index.min(fields.len());
+                    fields.shift_insert(index, name.clone(), String::new());
+                }
+            }
+        }
+        collection.last_id = collection.last_id.max(note.note_id);
+        collection.notes.insert(
+            note.note_id,
+            StoredNote {
+                model: note.model_name,
+                fields,
+                tags: note.tags,
+                cards: note.cards,
+            },
+        );
+    }
+
+    pub fn contains_note(&self, id: usize) -> bool {
+        self.collection.borrow().notes.contains_key(&id)
+    }
+
+    pub fn contains_card(&self, id: usize) -> bool {
+        self.collection.borrow().cards.contains_key(&id)
+    }
+}
+
+/// Render a card template like anki.
+///
+/// Supports `{{#field}}`/`{{^field}}` sections and filters in the form `{{filter:field}}`,
+/// of which only `text` changes the value. Unknown fields are kept as is.
+fn render_template(template: &str, fields: &IndexMap<String, String>, front_side: &str) -> String {
+    let mut res = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        res.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 2..end].trim();
+        let after = &rest[end + 2..];
+        rest = after;
+
+        if let Some((name, inverted)) = tag
+            .strip_prefix('#')
+            .map(|name| (name, false))
+            .or_else(|| tag.strip_prefix('^').map(|name| (name, true)))
+        {
+            let name = name.trim();
+            let close = format!("{{{{/{name}}}}}");
+            let (inner, remaining) = after.split_once(&close).unwrap_or((after, ""));
+            let non_empty = fields
+                .get(name)
+                .is_some_and(|value| !value.trim().is_empty());
+            if non_empty != inverted {
+                res.push_str(&render_template(inner, fields, front_side));
+            }
+            rest = remaining;
+            continue;
+        }
+
+        let mut parts = tag.rsplit(':');
+        let name = parts.next().unwrap_or_default().trim();
+        let filters = parts.collect::<Vec<_>>();
+        let value = match name {
+            "FrontSide" => Some(front_side.to_owned()),
+            _ => fields.get(name).cloned(),
+        };
+        match value {
+            Some(_) if filters.contains(&"type") => {}
+            Some(value) if filters.contains(&"text") => res.push_str(&strip_html(&value)),
+            Some(value) => res.push_str(&value),
+            None => res.push_str(&format!("{{{{{tag}}}}}")),
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+fn strip_html(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut in_tag = false;
+    for c in value.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => res.push(c),
+            _ => {}
+        }
+    }
+    res
+}
+
+impl Backend for InMemory {
+    fn deck_names(&self) -> Result<Vec<String>> {
+        Ok(self.collection.borrow().decks.keys().cloned().collect())
+    }
+
+    fn create_deck(&self, deck: &str) -> Result<Option<usize>> {
+        let mut collection = self.collection.borrow_mut();
+        // like anki, parent decks are created as well
+        let mut prefix = String::new();
+        let mut id = None;
+        for part in deck.split("::") {
+            if !prefix.is_empty() {
+                prefix.push_str("::");
+            }
+            prefix.push_str(part);
+            id = Some(match collection.decks.get(&prefix) {
+                Some(id) => *id,
+                None => {
+                    let id = collection.next_id();
+                    collection.decks.insert(prefix.clone(), id);
+                    id
+                }
+            });
+        }
+        Ok(id)
+    }
+
+    fn get_deck_config(&self, deck: &str) -> Result<DeckConfig> {
+        let mut collection = self.collection.borrow_mut();
+        if !collection.decks.contains_key(deck) {
+            bail!("deck was not found: {}", deck);
+        }
+        let id = collection
+            .deck_config_ids
+            .get(deck)
+            .copied()
+            .unwrap_or(DEFAULT_DECK_CONFIG);
+        collection
+            .deck_configs_mut()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| eyre!("options group was not found: {}", id))
+    }
+
+    fn save_deck_config(&self, config: &DeckConfig) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        match collection.deck_configs_mut().get_mut(&config.id) {
+            Some(existing) => *existing = config.clone(),
+            None => bail!("options group was not found: {}", config.id),
+        }
+        Ok(())
+    }
+
+    fn set_deck_config_id(&self, decks: &[String], config_id: usize) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        if !collection.deck_configs_mut().contains_key(&config_id) {
+            bail!("options group was not found: {}", config_id);
+        }
+        if let Some(deck) = decks
+            .iter()
+            .find(|deck| !collection.decks.contains_key(*deck))
+        {
+            bail!("deck was not found: {}", deck);
+        }
+        for deck in decks {
+            collection.deck_config_ids.insert(deck.clone(), config_id);
+        }
+        Ok(())
+    }
+
+    fn clone_deck_config_id(&self, name: &str, clone_from: usize) -> Result<usize> {
+        let mut collection = self.collection.borrow_mut();
+        let Some(mut config) = collection.deck_configs_mut().get(&clone_from).cloned() else {
+            bail!("options group was not found: {}", clone_from);
+        };
+        let id = collection.next_id();
+        config.id = id;
+        config.name = String::from(name);
+        collection.deck_configs.insert(id, config);
+        Ok(id)
+    }
+
+    fn model_names(&self) -> Result<Vec<String>> {
+        Ok(self.collection.borrow().models.keys().cloned().collect())
+    }
+
+    fn model_field_names(&self, model_names: &[String]) -> Result<Vec<Vec<String>>> {
+        let collection = self.collection.borrow();
+        model_names
+            .iter()
+            .map(|name| {
+                collection
+                    .models
+                    .get(name)
+                    .map(|model| model.fields.clone())
+                    .ok_or_else(|| eyre!("model was not found: {}", name))
+            })
+            .collect()
+    }
+
+    fn create_model(&self, data: &CreateModelData) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        if collection.models.contains_key(&data.model_name) {
+            bail!("Model name already exists");
+        }
+        let templates = data
+            .card_templates
+            .iter()
+            .map(|template| {
+                (
+                    template.get("Front").cloned().unwrap_or_default(),
+                    template.get("Back").cloned().unwrap_or_default(),
+                )
+            })
+            .collect();
+        let template_names = data
+            .card_templates
+            .iter()
+            .enumerate()
+            .map(|(ord, template)| {
+                template
+                    .get("Name")
+                    .cloned()
+                    .unwrap_or_else(|| format!("Card {}", ord + 1))
+            })
+            .collect();
+        collection.models.insert(
+            data.model_name.clone(),
+            Model {
+                fields: data.in_order_fields.clone(),
+                css: data.css.clone(),
+                templates,
+                template_names,
+            },
+        );
+        Ok(())
+    }
+
+    fn model_field_add(&self, model_name: &str, field_name: &str, index: usize) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        let Some(model) = collection.models.get_mut(model_name) else {
+            bail!("model was not found: {}", model_name);
+        };
+        if model.fields.iter().any(|field| field == field_name) {
+            bail!("field already exists: {}", field_name);
+        }
+        let index = index.min(model.fields.len());
+        model.fields.insert(index, String::from(field_name));
+        for note in collection.notes.values_mut() {
+            if note.model == model_name {
+                let index = index.min(note.fields.len());
+                note.fields
+                    .shift_insert(index, String::from(field_name), String::new());
+            }
+        }
+        Ok(())
+    }
+
+    fn model_templates(&self, model_name: &str) -> Result<IndexMap<String, CardTemplate>> {
+        let collection = self.collection.borrow();
+        let Some(model) = collection.models.get(model_name) else {
+            bail!("model was not found: {}", model_name);
+        };
+        Ok(model
+            .templates
+            .iter()
+            .enumerate()
+            .map(|(ord, (front, back))| {
+                (
+                    model.template_name(ord),
+                    CardTemplate {
+                        front: front.clone(),
+                        back: back.clone(),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn model_styling(&self, model_name: &str) -> Result<String> {
+        let collection = self.collection.borrow();
+        let Some(model) = collection.models.get(model_name) else {
+            bail!("model was not found: {}", model_name);
+        };
+        Ok(model.css.clone())
+    }
+
+    fn can_add_notes(&self, notes: &[Note]) -> Result<Vec<bool>> {
+        let collection = self.collection.borrow();
+        Ok(notes
+            .iter()
+            .map(|note| {
+                let Some(model) = collection.models.get(&note.model_name) else {
+                    return false;
+                };
+                let fields = model
+                    .fields
+                    .iter()
+                    .map(|name| {
+                        (
+                            name.clone(),
+                            note.fields.get(name).cloned().unwrap_or_default(),
+                        )
+                    })
+                    .collect();
+                collection.decks.contains_key(&note.deck_name)
+                    && note.fields.keys().all(|name| model.fields.contains(name))
+                    && !collection.is_duplicate(&note.model_name, &fields)
+            })
+            .collect())
+    }
+
+    fn add_notes(&self, notes: &[Note]) -> Result<Vec<Option<usize>>> {
+        let mut collection = self.collection.borrow_mut();
+        let mut ids = Vec::with_capacity(notes.len());
+        for note in notes {
+            let Some(model) = collection.models.get(&note.model_name).cloned() else {
+                bail!("model was not found: {}", note.model_name);
+            };
+            if !collection.decks.contains_key(&note.deck_name) {
+                bail!("deck was not found: {}", note.deck_name);
+            }
+            if let Some(name) = note.fields.keys().find(|name| !model.fields.contains(name)) {
+                bail!("field {} does not exist in model {}", name, note.model_name);
+            }
+            let fields: IndexMap<_, _> = model
+                .fields
+                .iter()
+                .map(|name| {
+                    (
+                        name.clone(),
+                        note.fields.get(name).cloned().unwrap_or_default(),
+                    )
+                })
+                .collect();
+            if collection.is_duplicate(&note.model_name, &fields) {
+                ids.push(None);
+                continue;
+            }
+
+            let mut fields = fields;
+            collection.attach_media(note, &mut fields)?;
+            let note_id = collection.next_id();
+            let cards = (0..model.templates.len().max(1))
+                .map(|ord| {
+                    let card_id = collection.next_id();
+                    collection.cards.insert(
+                        card_id,
+                        Card {
+                            note: note_id,
+                            deck: note.deck_name.clone(),
+                            ord,
+                            review: Review::default(),
+                        },
+                    );
+                    card_id
+                })
+                .collect();
+            collection.notes.insert(
+                note_id,
+                StoredNote {
+                    model: note.model_name.clone(),
+                    fields,
+                    tags: note.tags.clone(),
+                    cards,
+                },
+            );
+            ids.push(Some(note_id));
+        }
+
+        Ok(ids)
+    }
+
+    fn update_note(&self, id: usize, note: &Note) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        let stored = collection
+            .notes
+            .get_mut(&id)
+            .ok_or_else(|| eyre!("note was not found: {}", id))?;
+        if let Some(name) = note
+            .fields
+            .keys()
+            .find(|name| !stored.fields.contains_key(*name))
+        {
+            bail!("field {} does not exist in model {}", name, stored.model);
+        }
+        let mut fields = stored.fields.clone();
+        for (name, value) in &note.fields {
+            fields.insert(name.clone(), value.clone());
+        }
+        collection.attach_media(note, &mut fields)?;
+        if let Some(stored) = collection.notes.get_mut(&id) {
+            stored.fields = fields;
+            stored.tags.clone_from(&note.tags);
+        }
+        Ok(())
+    }
+
+    fn find_notes(&self, query: &str) -> Result<Vec<usize>> {
+        let expr = search::parse(query)?;
+        let collection = self.collection.borrow();
+        Ok(collection
+            .notes
+            .iter()
+            .filter(|(id, note)| {
+                let cards = note
+                    .cards
+                    .iter()
+                    .filter_map(|card_id| collection.cards.get(card_id))
+                    .collect();
+                expr.matches(&SearchNote {
+                    id: **id,
+                    note,
+                    cards,
+                })
+            })
+            .map(|(id, _)| *id)
+            .collect())
+    }
+
+    fn notes_info(&self, ids: &[usize]) -> Result<Vec<NoteInfo>> {
+        let collection = self.collection.borrow();
+        ids.iter()
+            .map(|id| {
+                let note = collection
+                    .notes
+                    .get(id)
+                    .ok_or_else(|| eyre!("note was not found: {}", id))?;
+                Ok(NoteInfo {
+                    note_id: *id,
+                    model_name: note.model.clone(),
+                    fields: note
+                        .fields
+                        .iter()
+                        .enumerate()
+                        .map(|(order, (name, value))| {
+                            (
+                                name.clone(),
+                                NoteInfoField {
+                                    value: value.clone(),
+                                    order,
+                                },
+                            )
+                        })
+                        .collect(),
+                    tags: note.tags.clone(),
+                    cards: note.cards.clone(),
+                })
+            })
+            .collect()
+    }
+
+    fn cards_info(&self, ids: &[usize]) -> Result<Vec<CardInfo>> {
+        let collection = self.collection.borrow();
+        ids.iter()
+            .map(|id| {
+                let card = collection
+                    .cards
+                    .get(id)
+                    .ok_or_else(|| eyre!("card was not found: {}", id))?;
+                let note = &collection.notes[&card.note];
+                let model = collection
+                    .models
+                    .get(&note.model)
+                    .cloned()
+                    .unwrap_or_default();
+                let (front, back) = model.templates.get(card.ord).cloned().unwrap_or_default();
+                let question = render_template(&front, &note.fields, "");
+                let answer = render_template(&back, &note.fields, &question);
+                Ok(CardInfo {
+                    answer,
+                    question,
+                    deck_name: card.deck.clone(),
+                    model_name: note.model.clone(),
+                    field_order: i32::try_from(card.ord).unwrap_or_default(),
+                    fields: note
+                        .fields
+                        .iter()
+                        .enumerate()
+                        .map(|(order, (name, value))| {
+                            (
+                                name.clone(),
+                                NoteInfoField {
+                                    value: value.clone(),
+                                    order,
+                                },
+                            )
+                        })
+                        .collect::<HashMap<_, _>>(),
+                    css: model.css,
+                    card_id: *id,
+                    interval: card.review.interval,
+                    note: card.note,
+                    ord: i32::try_from(card.ord).unwrap_or_default(),
+                    r#type: 0,
+                    queue: card.review.queue,
+                    due: card
+                        .review
+                        .due
+                        .unwrap_or_else(|| i32::try_from(card.note).unwrap_or_default()),
+                    reps: card.review.reps,
+                    lapses: card.review.lapses,
+                    left: 0,
+                    r#mod: 0,
+                })
+            })
+            .collect()
+    }
+
+    fn suspend(&self, cards: &[usize]) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        for id in cards {
+            let card = collection
+                .cards
+                .get_mut(id)
+                .ok_or_else(|| eyre!("card was not found: {}", id))?;
+            card.review.queue = QUEUE_SUSPENDED;
+        }
+        Ok(())
+    }
+
+    fn unsuspend(&self, cards: &[usize]) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        for id in cards {
+            let card = collection
+                .cards
+                .get_mut(id)
+                .ok_or_else(|| eyre!("card was not found: {}", id))?;
+            if card.review.queue == QUEUE_SUSPENDED {
+                card.review.queue = if card.review.reps == 0 {
+                    QUEUE_NEW
+                } else {
+                    QUEUE_REVIEW
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn are_suspended(&self, cards: &[usize]) -> Result<Vec<Option<bool>>> {
+        let collection = self.collection.borrow();
+        Ok(cards
+            .iter()
+            .map(|id| {
+                collection
+                    .cards
+                    .get(id)
+                    .map(|card| card.review.queue == QUEUE_SUSPENDED)
+            })
+            .collect())
+    }
+
+    fn forget_cards(&self, cards: &[usize]) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        for id in cards {
+            let card = collection
+                .cards
+                .get_mut(id)
+                .ok_or_else(|| eyre!("card was not found: {}", id))?;
+            card.review = Review {
+                reps: card.review.reps,
+                lapses: card.review.lapses,
+                ..Review::default()
+            };
+        }
+        Ok(())
+    }
+
+    fn relearn_cards(&self, cards: &[usize]) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        for id in cards {
+            let card = collection
+                .cards
+                .get_mut(id)
+                .ok_or_else(|| eyre!("card was not found: {}", id))?;
+            // like anki, new cards can't be relearned
+            if card.review.queue == QUEUE_REVIEW {
+                card.review.queue = QUEUE_LEARNING;
+            }
+        }
+        Ok(())
+    }
+
+    fn store_media_file(&self, data: &MediaData) -> Result<String> {
+        self.collection.borrow_mut().store_media(data)
+    }
+
+    fn retrieve_media_file(&self, filename: &str) -> Result<Option<String>> {
+        let collection = self.collection.borrow();
+        let Some(media) = collection.media.get(filename) else {
+            return Ok(None);
+        };
+        Ok(
+            media_content(media)?
+                .map(|data| base64::engine::general_purpose::STANDARD.encode(data)),
+        )
+    }
+
+    fn active_profile(&self) -> Result<String> {
+        Ok(self.collection.borrow_mut().profiles()[0].clone())
+    }
+
+    fn load_profile(&self, name: &str) -> Result<()> {
+        let mut collection = self.collection.borrow_mut();
+        let profiles = collection.profiles();
+        let Some(index) = profiles.iter().position(|profile| profile == name) else {
+            bail!("profile does not exist: {}", name);
+        };
+        let profile = profiles.remove(index);
+        profiles.insert(0, profile);
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        debug!("nothing to sync for the in-memory backend");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::SingleOrMulti;
+
+    fn backend() -> InMemory {
+        let backend = InMemory::default();
+        backend.create_deck("Math::Geometry").unwrap();
+        backend
+            .create_model(&CreateModelData {
+                model_name: String::from("Basic"),
+                in_order_fields: vec![String::from("Front"), String::from("Back")],
+                css: String::new(),
+                is_cloze: false,
+                card_templates: vec![HashMap::from([
+                    (String::from("Front"), String::from("{{Front}}")),
+                    (String::from("Back"), String::from("{{Back}}")),
+                ])],
+            })
+            .unwrap();
+        backend
+    }
+
+    fn note(deck: &str, front: &str, tags: &[&str]) -> Note {
+        Note {
+            deck_name: String::from(deck),
+            model_name: String::from("Basic"),
+            fields: HashMap::from([
+                (String::from("Front"), String::from(front)),
+                (String::from("Back"), String::from("back")),
+            ]),
+            tags: tags.iter().map(|tag| String::from(*tag)).collect(),
+            audio: SingleOrMulti::default(),
+            video: SingleOrMulti::default(),
+            picture: SingleOrMulti::default(),
+        }
+    }
+
+    #[test]
+    fn add_and_find() {
+        let backend = backend();
+        let ids = backend
+            .add_notes(&[
+                note("Math::Geometry", "Pythagoras", &["generated"]),
+                note("Default", "Euler", &["anki-typst::id::Euler"]),
+                note("Default", "Pythagoras", &[]),
+            ])
+            .unwrap();
+        let [Some(pythagoras), Some(euler), None] = ids[..] else {
+            panic!("unexpected ids {ids:?}");
+        };
+        assert!(backend.add_notes(&[note("Missing", "x", &[])]).is_err());
+
+        let find = |query| backend.find_notes(query).unwrap();
+        assert_eq!(find("deck:Math"), [pythagoras]);
+        assert_eq!(find("deck:Math::*"), [pythagoras]);
+        assert_eq!(find("-deck:Math"), [euler]);
+        assert_eq!(find("tag:gen*"), [pythagoras]);
+        assert_eq!(find("tag:anki-typst::id::*"), [euler]);
+        assert_eq!(find("front:pyth*"), [pythagoras]);
+        assert_eq!(find("euler or pythagoras"), [pythagoras, euler]);
+        assert_eq!(find(&format!("nid:{euler}")), [euler]);
+    }
+
+    #[test]
+    fn update() {
+        let backend = backend();
+        let id = backend
+            .add_notes(&[note("Default", "Pythagoras", &["a"])])
+            .unwrap()[0]
+            .unwrap();
+        let mut changed = note("Default", "Pythagoras theorem", &["b"]);
+        changed.fields.remove("Back");
+        backend.update_note(id, &changed).unwrap();
+
+        let info = backend.notes_info(&[id]).unwrap().remove(0);
+        assert_eq!(info.fields["Front"].value, "Pythagoras theorem");
+        assert_eq!(info.fields["Back"].value, "back");
+        assert_eq!(info.tags, ["b"]);
+        assert!(backend.update_note(id + 100, &changed).is_err());
+    }
+
+    #[test]
+    fn suspend() {
+        let backend = backend();
+        let id = backend
+            .add_notes(&[note("Default", "Pythagoras", &[])])
+            .unwrap()[0]
+            .unwrap();
+        let cards = backend.notes_info(&[id]).unwrap().remove(0).cards;
+        assert_eq!(cards.len(), 1);
+
+        backend.suspend(&cards).unwrap();
+        assert_eq!(
+            backend.are_suspended(&[cards[0], 0]).unwrap(),
+            [Some(true), None]
+        );
+        backend.unsuspend(&cards).unwrap();
+        assert_eq!(backend.are_suspended(&cards).unwrap(), [Some(false)]);
+    }
+
+    #[test]
+    fn store_media() {
+        let backend = backend();
+        let media = |content: &str, delete_existing| MediaData {
+            filename: String::from("page.svg"),
+            inner: MediaDataInner::Data(base64::engine::general_purpose::STANDARD.encode(content)),
+            delete_existing,
+            fields: vec![String::from("Back")],
+        };
+        let content = |filename: &str| {
+            let data = backend.retrieve_media_file(filename).unwrap().unwrap();
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .unwrap()
+        };
+
+        assert_eq!(
+            backend.store_media_file(&media("a", false)).unwrap(),
+            "page.svg"
+        );
+        // the same content keeps the name
+        assert_eq!(
+            backend.store_media_file(&media("a", false)).unwrap(),
+            "page.svg"
+        );
+        // a different content gets a new name, unless the existing file is replaced
+        let renamed = backend.store_media_file(&media("b", false)).unwrap();
+        assert_ne!(renamed, "page.svg");
+        assert!(
+            renamed.starts_with("page-") && renamed.ends_with(".svg"),
+            "{renamed}"
+        );
+        assert_eq!(content("page.svg"), b"a");
+        assert_eq!(content(&renamed), b"b");
+        assert_eq!(
+            backend.store_media_file(&media("c", true)).unwrap(),
+            "page.svg"
+        );
+        assert_eq!(content("page.svg"), b"c");
+
+        // attached media uses the name it was stored with
+        let mut attached = note("Default", "Pythagoras", &[]);
+        attached.picture = SingleOrMulti::Multi(vec![media("d", false)]);
+        let id = backend.add_notes(&[attached]).unwrap()[0].unwrap();
+        let back = &backend.notes_info(&[id]).unwrap()[0].fields["Back"].value;
+        assert!(back.starts_with("back<img src=\"page-"), "{back}");
+    }
+}
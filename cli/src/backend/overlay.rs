@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+use indexmap::IndexMap;
+use tracing::debug;
+
+use crate::api::{CardInfo, CardTemplate, CreateModelData, DeckConfig, MediaData, Note, NoteInfo};
+use crate::backend::{Backend, InMemory};
+
+/// A [`Backend`] which reads from another backend and keeps all writes in memory, e.g. for a dry run.
+///
+/// Decks and models are copied when it is created, notes and cards only when they are changed.
+/// Everything else is read from the other backend as needed.
+pub struct Overlay<B> {
+    inner: B,
+    /// Decks, models and the notes and cards which were created or changed
+    local: InMemory,
+    /// Decks and models of `inner`, to tell duplicates from notes `inner` can't add
+    inner_decks: HashSet<String>,
+    inner_models: HashSet<String>,
+}
+
+impl<B: Backend> Overlay<B> {
+    pub fn new(inner: B) -> Result<Self> {
+        debug!("creating overlay of backend");
+        let local = InMemory::without_notes(&inner)?;
+        Ok(Self {
+            inner_decks: local.deck_names()?.into_iter().collect(),
+            inner_models: local.model_names()?.into_iter().collect(),
+            inner,
+            local,
+        })
+    }
+
+    /// Copy the notes `ids` and their cards from `inner`, unless they are already copied.
+    fn copy_notes(&self, ids: &[usize]) -> Result<()> {
+        let ids = ids
+            .iter()
+            .copied()
+            .filter(|id| !self.local.contains_note(*id))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        // same as for fetching the notes, the responses would get too large otherwise
+        for ids in ids.chunks(400) {
+            let notes = self.inner.notes_info(ids)?;
+            let card_ids = notes
+                .iter()
+                .flat_map(|note| note.cards.clone())
+                .collect::<Vec<_>>();
+            let mut cards = self.inner.cards_info(&card_ids)?.into_iter();
+            for note in notes {
+                let cards = cards.by_ref().take(note.cards.len()).collect();
+                self.local.insert_note(note, cards);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the notes of the cards `ids` from `inner`, unless they are already copied.
+    fn copy_cards(&self, ids: &[usize]) -> Result<()> {
+        let ids = ids
+            .iter()
+            .copied()
+            .filter(|id| !self.local.contains_card(*id))
+            .collect::<Vec<_>>();
+        for ids in ids.chunks(400) {
+            let notes = self
+                .inner
+                .cards_info(ids)?
+                .into_iter()
+                .map(|card| card.note)
+                .collect::<Vec<_>>();
+            self.copy_notes(&notes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the results for `ids` from `local` for the ids which `is_local` and from `inner` for all others,
+/// in the order of `ids`.
+fn read_split<T>(
+    ids: &[usize],
+    is_local: impl Fn(usize) -> bool,
+    local: impl FnOnce(&[usize]) -> Result<Vec<T>>,
+    inner: impl FnOnce(&[usize]) -> Result<Vec<T>>,
+) -> Result<Vec<T>> {
+    let (local_ids, inner_ids): (Vec<_>, Vec<_>) = ids.iter().partition(|id| is_local(**id));
+    let mut local = local(&local_ids)?.into_iter();
+    let mut inner = if inner_ids.is_empty() {
+        Vec::new().into_iter()
+    } else {
+        inner(&inner_ids)?.into_iter()
+    };
+    ids.iter()
+        .map(|id| {
+            if is_local(*id) {
+                local.next()
+            } else {
+                inner.next()
+            }
+            .ok_or_else(|| eyre!("missing result for {}", id))
+        })
+        .collect()
+}
+
+impl<B: Backend> Backend for Overlay<B> {
+    fn deck_names(&self) -> Result<Vec<String>> {
+        self.local.deck_names()
+    }
+
+    fn create_deck(&self, deck: &str) -> Result<Option<usize>> {
+        self.local.create_deck(deck)
+    }
+
+    fn get_deck_config(&self, deck: &str) -> Result<DeckConfig> {
+        self.local.get_deck_config(deck)
+    }
+
+    fn save_deck_config(&self, config: &DeckConfig) -> Result<()> {
+        self.local.save_deck_config(config)
+    }
+
+    fn set_deck_config_id(&self, decks: &[String], config_id: usize) -> Result<()> {
+        self.local.set_deck_config_id(decks, config_id)
+    }
+
+    fn clone_deck_config_id(&self, name: &str, clone_from: usize) -> Result<usize> {
+        self.local.clone_deck_config_id(name, clone_from)
+    }
+
+    fn model_names(&self) -> Result<Vec<String>> {
+        self.local.model_names()
+    }
+
+    fn model_field_names(&self, model_names: &[String]) -> Result<Vec<Vec<String>>> {
+        self.local.model_field_names(model_names)
+    }
+
+    fn create_model(&self, data: &CreateModelData) -> Result<()> {
+        self.local.create_model(data)
+    }
+
+    fn model_templates(&self, model_name: &str) -> Result<IndexMap<String, CardTemplate>> {
+        self.local.model_templates(model_name)
+    }
+
+    fn model_styling(&self, model_name: &str) -> Result<String> {
+        self.local.model_styling(model_name)
+    }
+
+    fn model_field_add(&self, model_name: &str, field_name: &str, index: usize) -> Result<()> {
+        // notes which are copied later get the field from their model
+        self.local.model_field_add(model_name, field_name, index)
+    }
+
+    fn add_notes(&self, notes: &[Note]) -> Result<Vec<Option<usize>>> {
+        // notes in decks or with models which only exist in the overlay can't be duplicates in `inner`
+        let duplicates = self
+            .inner
+            .can_add_notes(notes)?
+            .into_iter()
+            .zip(notes)
+            .map(|(can_add, note)| {
+                !can_add
+                    && self.inner_decks.contains(&note.deck_name)
+                    && self.inner_models.contains(&note.model_name)
+            })
+            .collect::<Vec<_>>();
+        duplicates
+            .into_iter()
+            .zip(notes)
+            .map(|(duplicate, note)| {
+                if duplicate {
+                    Ok(None)
+                } else {
+                    Ok(self
+                        .local
+                        .add_notes(std::slice::from_ref(note))?
+                        .pop()
+                        .flatten())
+                }
+            })
+            .collect()
+    }
+
+    fn can_add_notes(&self, notes: &[Note]) -> Result<Vec<bool>> {
+        let inner = self.inner.can_add_notes(notes)?;
+        let local = self.local.can_add_notes(notes)?;
+        Ok(inner
+            .into_iter()
+            .zip(local)
+            .zip(notes)
+            .map(|((inner, local), note)| {
+                let only_local = !self.inner_decks.contains(&note.deck_name)
+                    || !self.inner_models.contains(&note.model_name);
+                local && (inner || only_local)
+            })
+            .collect())
+    }
+
+    fn update_note(&self, id: usize, note: &Note) -> Result<()> {
+        self.copy_notes(&[id])?;
+        self.local.update_note(id, note)
+    }
+
+    fn find_notes(&self, query: &str) -> Result<Vec<usize>> {
+        let local = self.local.find_notes(query)?;
+        // copied notes may have been changed, so they are only found if they still match
+        let mut ids = self
+            .inner
+            .find_notes(query)?
+            .into_iter()
+            .filter(|id| !self.local.contains_note(*id))
+            .collect::<Vec<_>>();
+        ids.extend(local);
+        Ok(ids)
+    }
+
+    fn notes_info(&self, ids: &[usize]) -> Result<Vec<NoteInfo>> {
+        read_split(
+            ids,
+            |id| self.local.contains_note(id),
+            |ids| self.local.notes_info(ids),
+            |ids| self.inner.notes_info(ids),
+        )
+    }
+
+    fn cards_info(&self, ids: &[usize]) -> Result<Vec<CardInfo>> {
+        read_split(
+            ids,
+            |id| self.local.contains_card(id),
+            |ids| self.local.cards_info(ids),
+            |ids| self.inner.cards_info(ids),
+        )
+    }
+
+    fn suspend(&self, cards: &[usize]) -> Result<()> {
+        self.copy_cards(cards)?;
+        self.local.suspend(cards)
+    }
+
+    fn unsuspend(&self, cards: &[usize]) -> Result<()> {
+        self.copy_cards(cards)?;
+        self.local.unsuspend(cards)
+    }
+
+    fn are_suspended(&self, cards: &[usize]) -> Result<Vec<Option<bool>>> {
+        read_split(
+            cards,
+            |id| self.local.contains_card(id),
+            |ids| self.local.are_suspended(ids),
+            |ids| self.inner.are_suspended(ids),
+        )
+    }
+
+    fn forget_cards(&self, cards: &[usize]) -> Result<()> {
+        self.copy_cards(cards)?;
+        self.local.forget_cards(cards)
+    }
+
+    fn relearn_cards(&self, cards: &[usize]) -> Result<()> {
+        self.copy_cards(cards)?;
+        self.local.relearn_cards(cards)
+    }
+
+    fn store_media_file(&self, data: &MediaData) -> Result<String> {
+        self.local.store_media_file(data)
+    }
+
+    fn retrieve_media_file(&self, filename: &str) -> Result<Option<String>> {
+        match self.local.retrieve_media_file(filename)? {
+            Some(data) => Ok(Some(data)),
+            None => self.inner.retrieve_media_file(filename),
+        }
+    }
+
+    fn active_profile(&self) -> Result<String> {
+        self.inner.active_profile()
+    }
+
+    fn load_profile(&self, name: &str) -> Result<()> {
+        bail!("can't switch to profile {} in a dry run", name)
+    }
+
+    fn sync(&self) -> Result<()> {
+        debug!("nothing to sync for the overlay backend");
+        Ok(())
+    }
+}
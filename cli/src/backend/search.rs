@@ -0,0 +1,320 @@
+//! A subset of the Anki search syntax.
+//!
+//! Supported are
+//! - `*` to match everything
+//! - `key:value` terms, e.g. `deck:Math`, `tag:generated` or `front:*Pythagoras*`
+//! - plain text which matches any field
+//! - `*` and `_` as wildcards and `\` to escape them
+//! - negation with `-`, grouping with `(...)`, `and` (implicit) and `or`
+//!
+//! See <https://docs.ankiweb.net/searching.html>
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    All,
+    Term { key: Option<String>, value: String },
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+/// Something which can be matched by a search term.
+pub trait Searchable {
+    /// Whether the item matches `key:value` or just `value` if `key` is `None`.
+    ///
+    /// `value` is a pattern which should be matched with [`glob_match`].
+    fn matches_term(&self, key: Option<&str>, value: &str) -> bool;
+}
+
+impl Expr {
+    pub fn matches(&self, item: &impl Searchable) -> bool {
+        match self {
+            Self::All => true,
+            Self::Term { key, value } => item.matches_term(key.as_deref(), value),
+            Self::Not(expr) => !expr.matches(item),
+            Self::And(exprs) => exprs.iter().all(|expr| expr.matches(item)),
+            Self::Or(exprs) => exprs.iter().any(|expr| expr.matches(item)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    Or,
+    Text(String),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            _ => {
+                let mut text = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if !quoted && (c.is_whitespace() || c == '(' || c == ')') {
+                        break;
+                    }
+                    chars.next();
+                    match c {
+                        '"' => quoted = !quoted,
+                        '\\' => {
+                            let escaped = chars
+                                .next()
+                                .ok_or_else(|| eyre!("query ends with `\\`: {}", query))?;
+                            // keep the escape for wildcards, so they can be matched literally
+                            if escaped != '"' {
+                                text.push('\\');
+                            }
+                            text.push(escaped);
+                        }
+                        c => text.push(c),
+                    }
+                }
+                if quoted {
+                    bail!("unclosed quote in query: {}", query);
+                }
+                tokens.push(match text.to_lowercase().as_str() {
+                    "or" => Token::Or,
+                    "and" => continue,
+                    _ => Token::Text(text),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+pub fn parse(query: &str) -> Result<Expr> {
+    let tokens = tokenize(query)?;
+    let mut pos = 0;
+    let expr = parse_or(&tokens, &mut pos)?;
+    if pos != tokens.len() {
+        bail!("unexpected `)` in query: {}", query);
+    }
+    Ok(expr)
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize) -> Result<Expr> {
+    let mut exprs = vec![parse_and(tokens, pos)?];
+    while tokens.get(*pos) == Some(&Token::Or) {
+        *pos += 1;
+        exprs.push(parse_and(tokens, pos)?);
+    }
+    Ok(if exprs.len() == 1 {
+        exprs.remove(0)
+    } else {
+        Expr::Or(exprs)
+    })
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize) -> Result<Expr> {
+    let mut exprs = Vec::new();
+    while let Some(token) = tokens.get(*pos) {
+        if matches!(token, Token::Or | Token::Close) {
+            break;
+        }
+        exprs.push(parse_unary(tokens, pos)?);
+    }
+    Ok(match exprs.len() {
+        0 => Expr::All,
+        1 => exprs.remove(0),
+        _ => Expr::And(exprs),
+    })
+}
+
+fn parse_unary(tokens: &[Token], pos: &mut usize) -> Result<Expr> {
+    let Some(token) = tokens.get(*pos) else {
+        bail!("unexpected end of query");
+    };
+    *pos += 1;
+    match token {
+        Token::Not => Ok(Expr::Not(Box::new(parse_unary(tokens, pos)?))),
+        Token::Open => {
+            let expr = parse_or(tokens, pos)?;
+            if tokens.get(*pos) != Some(&Token::Close) {
+                bail!("missing `)` in query");
+            }
+            *pos += 1;
+            Ok(expr)
+        }
+        Token::Text(text) if text == "*" => Ok(Expr::All),
+        Token::Text(text) => Ok(split_term(text)),
+        Token::Close | Token::Or => bail!("unexpected token {:?} in query", token),
+    }
+}
+
+fn split_term(text: &str) -> Expr {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ':' if !escaped => {
+                return Expr::Term {
+                    key: Some(text[..i].to_lowercase()),
+                    value: String::from(&text[i + 1..]),
+                };
+            }
+            _ => escaped = false,
+        }
+    }
+    Expr::Term {
+        key: None,
+        value: String::from(text),
+    }
+}
+
+/// Escape `value` so it is matched literally.
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '*' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Build a search term `key:value` which matches `value` literally.
+pub fn term(key: &str, value: &str) -> String {
+    format!("\"{key}:{}\"", escape(value))
+}
+
+/// Case insensitive match of `text` against `pattern`.
+///
+/// `*` matches any number of characters, `_` a single one. Both can be escaped with `\`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Pattern {
+        Any,
+        Single,
+        Char(char),
+    }
+
+    let mut parsed = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        parsed.push(match c {
+            '*' => Pattern::Any,
+            '_' => Pattern::Single,
+            '\\' => Pattern::Char(chars.next().unwrap_or('\\')),
+            c => Pattern::Char(c),
+        });
+    }
+    let text: Vec<char> = text.chars().collect();
+
+    // classic wildcard matching with backtracking to the last `*`
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match parsed.get(p) {
+            Some(Pattern::Any) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(Pattern::Single) => {
+                p += 1;
+                t += 1;
+            }
+            Some(Pattern::Char(c)) if c.to_lowercase().eq(text[t].to_lowercase()) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    parsed[p..].iter().all(|p| *p == Pattern::Any)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(key: Option<&str>, value: &str) -> Expr {
+        Expr::Term {
+            key: key.map(String::from),
+            value: String::from(value),
+        }
+    }
+
+    #[test]
+    fn quoted_terms() {
+        assert_eq!(
+            parse(r#""deck:Math Stuff" "front:a \"b\"""#).unwrap(),
+            Expr::And(vec![
+                term(Some("deck"), "Math Stuff"),
+                term(Some("front"), "a \"b\""),
+            ])
+        );
+        // wildcards stay escaped, so they are matched literally
+        assert_eq!(
+            parse(&super::term("tag", "a_b")).unwrap(),
+            term(Some("tag"), r"a\_b")
+        );
+        assert!(parse("\"deck:Math").is_err());
+    }
+
+    #[test]
+    fn negation_and_grouping() {
+        assert_eq!(
+            parse("-tag:todo (deck:A or Deck:B) Pythagoras").unwrap(),
+            Expr::And(vec![
+                Expr::Not(Box::new(term(Some("tag"), "todo"))),
+                Expr::Or(vec![term(Some("deck"), "A"), term(Some("deck"), "B")]),
+                term(None, "Pythagoras"),
+            ])
+        );
+        assert_eq!(parse("*").unwrap(), Expr::All);
+        assert!(parse("(deck:A").is_err());
+        assert!(parse("deck:A)").is_err());
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("Math::*", "Math::Geometry"));
+        assert!(!glob_match("Math::*", "Math"));
+        assert!(glob_match(
+            "anki-typst::id::*",
+            "anki-typst::id::Pythagoras"
+        ));
+        assert!(glob_match("gen*", "Generated"));
+        assert!(glob_match("a_c", "abc"));
+        assert!(!glob_match(r"a\_c", "abc"));
+        assert!(glob_match(r"a\*c", "a*c"));
+        assert!(glob_match("*theorem*", "Pythagoras theorem"));
+        assert!(!glob_match("*theorem", "theorems"));
+    }
+}
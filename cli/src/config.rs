@@ -1,12 +1,19 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
 use color_eyre::eyre::eyre;
 use color_eyre::{Help, Result};
 use regex_lite::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::info;
 
-#[derive(Debug)]
+use crate::api::ClientOptions;
+use crate::deck_options;
+use crate::interface::TypstOptions;
+use crate::template;
+use crate::Theme;
+
+#[derive(Debug, Clone)]
 pub struct RegexString {
     re: Regex,
     re_str: String,
@@ -26,26 +33,258 @@ impl<'de> Deserialize<'de> for RegexString {
     }
 }
 
-#[derive(Debug)]
+impl RegexString {
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.re.is_match(haystack)
+    }
+
+    /// Replace all matches with `replacement`, which may refer to groups like `$1`.
+    pub fn replace_all(&self, haystack: &str, replacement: &str) -> String {
+        self.re.replace_all(haystack, replacement).into_owned()
+    }
+}
+
+/// Settings for a single note model.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ModelConfig {
+    /// Field which stores the typst id.
+    pub id_field: Option<String>,
+    /// Field which stores the location in the typst sources.
+    pub source_field: Option<String>,
+    /// Template for each image of the fields rendered by typst, see [`crate::template`].
+    pub image_template: Option<String>,
+    /// Templates for the images of single fields, they take precedence over `image_template`.
+    pub field_templates: HashMap<String, String>,
+    /// Where the text of fields rendered as images is written to, so it can be searched.
+    pub text_layer: TextLayer,
+    /// Anki fields by typst field name, see [`crate::rewrite::map_fields`]
+    pub field_map: HashMap<String, FieldMapping>,
+    /// Add the typst fields which the model doesn't have to it
+    pub add_missing_fields: bool,
+}
+
+/// Where a typst field is written to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum FieldMapping {
+    /// Into the anki field with this name
+    Field(String),
+    /// `false` drops the field, `true` keeps its name
+    Keep(bool),
+}
+
+/// Where the text of fields rendered as images is written to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextLayer {
+    /// Don't write the text
+    #[default]
+    None,
+    /// Into a hidden `<span>` after the images
+    Span,
+    /// Into the field `<field>_text` if the model has it
+    Field,
+}
+
+/// Options for new cards, reviews and lapses of decks.
+///
+/// Only the given options are changed, all others keep their value in anki.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DeckPreset {
+    pub new_per_day: Option<u32>,
+    pub reviews_per_day: Option<u32>,
+    /// In minutes
+    pub learning_steps: Option<Vec<f64>>,
+    /// In minutes
+    pub relearning_steps: Option<Vec<f64>>,
+    /// In days
+    pub graduating_interval: Option<u32>,
+    /// In days
+    pub easy_interval: Option<u32>,
+    /// In days
+    pub maximum_interval: Option<u32>,
+    pub desired_retention: Option<f64>,
+    pub fsrs_params: Option<Vec<f64>>,
+    /// Other options, merged into the options group as anki stores it
+    pub raw: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Use the deck preset `preset` for all decks matching `decks`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeckOptionsRule {
+    pub decks: RegexString,
+    pub preset: String,
+}
+
+/// Rules which change the notes before they are written to anki, see [`crate::rewrite`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Rewrite {
+    pub decks: Vec<DeckRewrite>,
+    pub tags: Vec<TagRewrite>,
+    pub fields: Vec<FieldRewrite>,
+}
+
+impl Rewrite {
+    /// Append the rules of `other` after the own ones.
+    pub fn extend(&mut self, other: &Rewrite) {
+        self.decks.extend(other.decks.iter().cloned());
+        self.tags.extend(other.tags.iter().cloned());
+        self.fields.extend(other.fields.iter().cloned());
+    }
+}
+
+/// Conditions on a note, all given ones have to match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NoteFilter {
+    pub deck: Option<RegexString>,
+    pub model: Option<RegexString>,
+    /// Matches only notes with a typst id
+    pub id: Option<RegexString>,
+}
+
+/// Replace all matches of `pattern` in the deck names of matching notes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeckRewrite {
+    #[serde(flatten)]
+    pub filter: NoteFilter,
+    pub pattern: RegexString,
+    /// May refer to groups of `pattern` like `$1`
+    #[serde(default)]
+    pub replace: String,
+}
+
+/// Change the tags of matching notes, in the order remove, rename, add.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TagRewrite {
+    #[serde(flatten)]
+    pub filter: NoteFilter,
+    #[serde(default)]
+    pub remove: Vec<RegexString>,
+    /// New name by old name
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+    #[serde(default)]
+    pub add: Vec<String>,
+}
+
+/// Rename the fields of matching notes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FieldRewrite {
+    #[serde(flatten)]
+    pub filter: NoteFilter,
+    /// New name by old name
+    pub rename: HashMap<String, String>,
+}
+
+/// A collection the notes are written to, selected with `--target`.
+///
+/// The connection settings default to the ones of the `anki` section.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Target {
+    #[serde(skip)]
+    pub name: String,
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+    pub profile: Option<String>,
+    /// Prepended to the deck of each note, e.g. `Shared::`
+    pub deck_prefix: String,
+    /// Added to each note
+    pub tags: Vec<String>,
+    pub theme: Option<Theme>,
+    /// Replaces the `hook` of the config
+    pub hook: Option<Vec<String>>,
+    /// Applied after the `rewrite` rules of the config
+    pub rewrite: Rewrite,
+}
+
+/// What happens to the cards of a note when its `revision` in typst changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RevisionPolicy {
+    /// Keep the scheduling of the cards
+    #[default]
+    Keep,
+    /// Reset the cards to new cards
+    Reset,
+    /// Put the cards into relearning
+    Relearn,
+}
+
+/// What happens to fields which were edited in anki since anki-typst wrote them last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnkiEditPolicy {
+    /// Keep the value of anki and warn about it
+    #[default]
+    Keep,
+    /// Write the value of typst
+    Overwrite,
+    /// Ask which value to keep
+    Prompt,
+}
+
+/// Which notes are fetched from anki to find already existing notes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FetchScope {
+    /// Notes in the decks used by the typst file
+    #[default]
+    Decks,
+    /// Notes with the tag `generated`
+    Generated,
+    /// Notes with the models used by the typst file
+    Models,
+    /// All notes of the collection
+    All,
+}
+
+#[derive(Debug, Clone)]
 pub struct Config {
     pub path: Option<PathBuf>,
     pub file_include: Vec<RegexString>,
     pub file_exclude: Vec<RegexString>,
     pub add_generated: bool,
     pub add_generation_date: Option<String>,
+    pub typst: TypstOptions,
+    pub anki: ClientOptions,
+    pub fetch_scope: FetchScope,
+    /// Whether to store the typst id in a tag
+    pub id_tag: bool,
+    /// Whether to store the location in the typst sources in a tag
+    pub source_tag: bool,
+    pub models: HashMap<String, ModelConfig>,
+    /// Deck presets by the name of their options group in anki
+    pub deck_presets: HashMap<String, DeckPreset>,
+    /// The first matching rule decides the preset of a deck
+    pub deck_options: Vec<DeckOptionsRule>,
+    /// Used for notes which don't set `on_revision` in typst
+    pub on_revision: RevisionPolicy,
+    /// Whether notes whose content changed in typst are updated in anki
+    pub update: bool,
+    /// Directory of the [`crate::journal`], `None` if it is disabled
+    pub journal_dir: Option<PathBuf>,
+    /// Number of runs which are kept in the journal
+    pub journal_runs: usize,
+    /// Anki profile which is written to
+    pub profile: Option<String>,
+    /// Named targets, in the order of their names
+    pub targets: BTreeMap<String, Target>,
+    /// The target which was selected with [`Config::with_target`], if any
+    pub target: Option<Target>,
+    /// Program and arguments which transform the notes, see [`crate::hook`]
+    pub hook: Vec<String>,
+    /// What happens to fields edited in anki, needs the journal to know the last written values
+    pub on_anki_edit: AnkiEditPolicy,
+    pub rewrite: Rewrite,
 }
 
 impl Config {
     pub fn load(add_generated: bool, add_generation_date: Option<String>) -> Result<Self> {
-        #[derive(Default, serde::Deserialize)]
-        struct ExternalConfig {
-            path: Option<PathBuf>,
-            #[serde(default)]
-            file_include: Vec<RegexString>,
-            #[serde(default)]
-            file_exclude: Vec<RegexString>,
-        }
-
         let project_dirs = directories_next::ProjectDirs::from("", "akida", "anki-typst")
             .expect("no valid home directory path could be found");
         let config_dir = project_dirs.config_dir();
@@ -54,36 +293,268 @@ impl Config {
         }
         let config_path = config_dir.join("config.toml");
 
-        let config: ExternalConfig = if !config_path.is_file() {
+        let config_text = if !config_path.is_file() {
             info!(
                 "no config file found. You can create one at {}",
                 config_path.to_string_lossy()
             );
-            ExternalConfig::default()
+            String::new()
         } else {
-            let config_text = std::fs::read_to_string(&config_path).with_note(|| {
+            std::fs::read_to_string(&config_path).with_note(|| {
                 eyre!(
                     "while reading config file from {}",
                     config_path.to_string_lossy()
                 )
-            })?;
-            toml::from_str(&config_text).with_note(|| {
-                eyre!(
-                    "while parsing config file from {}",
-                    config_path.to_string_lossy()
-                )
             })?
         };
 
+        Self::parse(
+            &config_text,
+            add_generated,
+            add_generation_date,
+            project_dirs.data_dir(),
+        )
+        .with_note(|| {
+            eyre!(
+                "while parsing config file from {}",
+                config_path.to_string_lossy()
+            )
+        })
+    }
+
+    /// Parse the content of a config file, the journal is stored in `data_dir`.
+    pub fn parse(
+        config_text: &str,
+        add_generated: bool,
+        add_generation_date: Option<String>,
+        data_dir: &Path,
+    ) -> Result<Self> {
+        #[derive(Default, serde::Deserialize)]
+        struct ExternalConfig {
+            path: Option<PathBuf>,
+            #[serde(default)]
+            file_include: Vec<RegexString>,
+            #[serde(default)]
+            file_exclude: Vec<RegexString>,
+            #[serde(default)]
+            typst: TypstOptions,
+            #[serde(default)]
+            anki: ClientOptions,
+            #[serde(default)]
+            fetch_scope: FetchScope,
+            id_tag: Option<bool>,
+            source_tag: Option<bool>,
+            #[serde(default)]
+            models: HashMap<String, ModelConfig>,
+            #[serde(default)]
+            deck_presets: HashMap<String, DeckPreset>,
+            #[serde(default)]
+            deck_options: Vec<DeckOptionsRule>,
+            #[serde(default)]
+            on_revision: RevisionPolicy,
+            #[serde(default)]
+            update: bool,
+            #[serde(default)]
+            journal: bool,
+            journal_runs: Option<usize>,
+            profile: Option<String>,
+            #[serde(default)]
+            targets: BTreeMap<String, Target>,
+            #[serde(default)]
+            hook: Vec<String>,
+            #[serde(default)]
+            rewrite: Rewrite,
+            on_anki_edit: Option<AnkiEditPolicy>,
+        }
+
+        let config: ExternalConfig = toml::from_str(config_text)?;
+
+        for (model, model_config) in &config.models {
+            let templates = model_config
+                .image_template
+                .iter()
+                .chain(model_config.field_templates.values());
+            for template in templates {
+                template::validate(template, &template::IMAGE_VARIABLES)
+                    .with_note(|| eyre!("in the config of model {}", model))?;
+            }
+        }
+
+        for rule in &config.deck_options {
+            if !config.deck_presets.contains_key(&rule.preset) {
+                return Err(eyre!(
+                    "unknown deck preset `{}` for decks matching \"{}\"",
+                    rule.preset,
+                    rule.decks.re_str
+                ));
+            }
+        }
+
+        for (name, preset) in &config.deck_presets {
+            if let Some(params) = preset.fsrs_params.as_deref() {
+                if deck_options::fsrs_key(params).is_none() {
+                    return Err(eyre!(
+                        "deck preset `{}` has {} FSRS parameters, but FSRS uses 17, 19 or 21",
+                        name,
+                        params.len()
+                    ));
+                }
+            }
+        }
+
+        if config.on_anki_edit.is_some() && !config.journal {
+            return Err(eyre!(
+                "`on_anki_edit` needs the journal to know the values written last"
+            ))
+            .suggestion("set `journal = true`");
+        }
+
         Ok(Self {
             path: config.path,
             file_include: config.file_include,
             file_exclude: config.file_exclude,
             add_generated,
             add_generation_date,
+            typst: config.typst,
+            anki: config.anki,
+            fetch_scope: config.fetch_scope,
+            id_tag: config.id_tag.unwrap_or(true),
+            source_tag: config.source_tag.unwrap_or(true),
+            models: config.models,
+            deck_presets: config.deck_presets,
+            deck_options: config.deck_options,
+            on_revision: config.on_revision,
+            update: config.update,
+            journal_dir: config.journal.then(|| data_dir.join("journal")),
+            journal_runs: config.journal_runs.unwrap_or(50),
+            profile: config.profile,
+            targets: config.targets,
+            target: None,
+            hook: config.hook,
+            rewrite: config.rewrite,
+            on_anki_edit: config.on_anki_edit.unwrap_or_default(),
         })
     }
 
+    /// A copy of the config which writes to the target `name`.
+    ///
+    /// Each target has its own journal, in `targets/<name>` of the journal directory.
+    pub fn with_target(&self, name: &str) -> Result<Self> {
+        let Some(target) = self.targets.get(name) else {
+            return Err(eyre!("unknown target `{}`", name)).with_note(|| {
+                eyre!(
+                    "available targets: {}",
+                    self.targets.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            });
+        };
+        let mut config = self.clone();
+        let mut target = target.clone();
+        target.name = String::from(name);
+        if let Some(endpoint) = &target.endpoint {
+            config.anki.endpoint = endpoint.clone();
+        }
+        if let Some(api_key) = &target.api_key {
+            config.anki.api_key = Some(api_key.clone());
+        }
+        if let Some(profile) = &target.profile {
+            config.profile = Some(profile.clone());
+        }
+        if let Some(hook) = &target.hook {
+            config.hook = hook.clone();
+        }
+        config.rewrite.extend(&target.rewrite);
+        config.journal_dir = config.journal_dir.map(|dir| dir.join("targets").join(name));
+        config.target = Some(target);
+        Ok(config)
+    }
+
+    /// Name of `deck` in the selected target.
+    pub fn target_deck(&self, deck: &str) -> String {
+        match &self.target {
+            Some(target) => format!("{}{}", target.deck_prefix, deck),
+            None => String::from(deck),
+        }
+    }
+
+    /// Tags which are added to each note in the selected target.
+    pub fn target_tags(&self) -> &[String] {
+        self.target
+            .as_ref()
+            .map(|target| target.tags.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Theme of the selected target, if any.
+    pub fn target_theme(&self) -> Option<Theme> {
+        self.target.as_ref()?.theme
+    }
+
+    /// Name of the field which stores the typst id of notes with `model`.
+    ///
+    /// Returns the configured field and whether it was explicitly configured.
+    pub fn id_field(&self, model: &str) -> (&str, bool) {
+        match self.models.get(model).and_then(|m| m.id_field.as_deref()) {
+            Some(field) => (field, true),
+            None => ("id", false),
+        }
+    }
+
+    /// Whether the location of notes in the typst sources is stored, in a tag or a field.
+    pub fn stores_sources(&self) -> bool {
+        self.source_tag
+            || self
+                .models
+                .values()
+                .any(|model| model.source_field.is_some())
+    }
+
+    /// Name of the field which stores the source location of notes with `model`, if any.
+    pub fn source_field(&self, model: &str) -> Option<&str> {
+        self.models.get(model)?.source_field.as_deref()
+    }
+
+    /// Anki fields by typst field name for notes with `model`, if any.
+    pub fn field_map(&self, model: &str) -> Option<&HashMap<String, FieldMapping>> {
+        self.models
+            .get(model)
+            .map(|model| &model.field_map)
+            .filter(|field_map| !field_map.is_empty())
+    }
+
+    pub fn add_missing_fields(&self, model: &str) -> bool {
+        self.models
+            .get(model)
+            .is_some_and(|model| model.add_missing_fields)
+    }
+
+    pub fn text_layer(&self, model: &str) -> TextLayer {
+        self.models
+            .get(model)
+            .map(|model| model.text_layer)
+            .unwrap_or_default()
+    }
+
+    /// Template for the images of `field` of notes with `model`, if any.
+    pub fn image_template(&self, model: &str, field: &str) -> Option<&str> {
+        let model = self.models.get(model)?;
+        model
+            .field_templates
+            .get(field)
+            .or(model.image_template.as_ref())
+            .map(String::as_str)
+    }
+
+    /// Name and options of the deck preset for `deck`, if any.
+    pub fn deck_preset(&self, deck: &str) -> Option<(&str, &DeckPreset)> {
+        let rule = self
+            .deck_options
+            .iter()
+            .find(|rule| rule.decks.re.is_match(deck))?;
+        let preset = self.deck_presets.get(&rule.preset)?;
+        Some((&rule.preset, preset))
+    }
+
     pub fn is_ignored(&self, path: &str) -> bool {
         if !self.file_include.is_empty() && !self.file_include.iter().any(|r| r.re.is_match(path)) {
             info!(
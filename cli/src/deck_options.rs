@@ -0,0 +1,188 @@
+//! Deck option presets from the config, applied to the options groups of anki.
+//!
+//! Each preset is an options group with the same name in anki.
+//! It is created as a copy of the `Default` group if it doesn't exist yet.
+
+use std::collections::{BTreeMap, HashMap};
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::{Help, Result};
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::api::DEFAULT_DECK_CONFIG;
+use crate::backend::Backend;
+use crate::config::{Config, DeckPreset};
+
+/// Keys under which anki stores the FSRS parameters and how many there are, depending on the
+/// version of FSRS.
+const FSRS_KEYS: [(&str, usize); 3] = [
+    ("fsrsParams6", 21),
+    ("fsrsParams5", 19),
+    ("fsrsWeights", 17),
+];
+
+/// Key under which anki stores the FSRS parameters `params`, if their number fits a version of FSRS.
+pub fn fsrs_key(params: &[f64]) -> Option<&'static str> {
+    FSRS_KEYS
+        .into_iter()
+        .find(|(_, len)| *len == params.len())
+        .map(|(key, _)| key)
+}
+
+/// Apply the deck presets of `config` to all matching `decks`.
+pub fn apply(backend: &dyn Backend, config: &Config, decks: &[String]) -> Result<()> {
+    let mut by_preset: BTreeMap<&str, (&DeckPreset, Vec<String>)> = BTreeMap::new();
+    for deck in decks {
+        if let Some((name, preset)) = config.deck_preset(deck) {
+            by_preset
+                .entry(name)
+                .or_insert_with(|| (preset, Vec::new()))
+                .1
+                .push(deck.clone());
+        }
+    }
+    if by_preset.is_empty() {
+        debug!("no deck presets for decks {}", decks.join(", "));
+        return Ok(());
+    }
+
+    let mut group_ids = HashMap::new();
+    for (name, (preset, decks)) in by_preset {
+        let id = match group_id(backend, &mut group_ids, name, &decks)? {
+            Some(id) => id,
+            None => {
+                let id = backend.clone_deck_config_id(name, DEFAULT_DECK_CONFIG)?;
+                info!("created options group {}", name);
+                group_ids.insert(String::from(name), id);
+                id
+            }
+        };
+        backend.set_deck_config_id(&decks, id)?;
+
+        let mut group = backend.get_deck_config(&decks[0])?;
+        let mut options = Value::Object(group.options.clone());
+        apply_preset(&mut options, &group.name, preset)?;
+        let Value::Object(options) = options else {
+            unreachable!("options are always an object");
+        };
+        if options == group.options {
+            debug!("options group {} is up to date", name);
+        } else {
+            group.options = options;
+            backend
+                .save_deck_config(&group)
+                .with_note(|| eyre!("while saving the options group {}", name))?;
+            info!("updated options group {}", name);
+        }
+        info!("decks {} use the options group {}", decks.join(", "), name);
+    }
+
+    Ok(())
+}
+
+/// Find the id of the options group `name`.
+///
+/// Anki can't list the options groups, so they are collected from the decks using them,
+/// first from `decks` and then from all decks.
+fn group_id(
+    backend: &dyn Backend,
+    group_ids: &mut HashMap<String, usize>,
+    name: &str,
+    decks: &[String],
+) -> Result<Option<usize>> {
+    if let Some(id) = group_ids.get(name) {
+        return Ok(Some(*id));
+    }
+    let other_decks = backend
+        .deck_names()?
+        .into_iter()
+        .filter(|deck| !decks.contains(deck))
+        .collect::<Vec<_>>();
+    for decks in [decks, &other_decks] {
+        for group in backend.get_deck_configs(decks)? {
+            group_ids.entry(group.name).or_insert(group.id);
+        }
+        if let Some(id) = group_ids.get(name) {
+            return Ok(Some(*id));
+        }
+    }
+    Ok(None)
+}
+
+/// Change the options of the group `name` which are set in `preset`.
+fn apply_preset(group: &mut Value, name: &str, preset: &DeckPreset) -> Result<()> {
+    if let Some(value) = preset.new_per_day {
+        set(group, &["new", "perDay"], json!(value));
+    }
+    if let Some(value) = preset.reviews_per_day {
+        set(group, &["rev", "perDay"], json!(value));
+    }
+    if let Some(value) = &preset.learning_steps {
+        set(group, &["new", "delays"], json!(value));
+    }
+    if let Some(value) = &preset.relearning_steps {
+        set(group, &["lapse", "delays"], json!(value));
+    }
+    if let Some(value) = preset.maximum_interval {
+        set(group, &["rev", "maxIvl"], json!(value));
+    }
+    if let Some(value) = preset.desired_retention {
+        set(group, &["desiredRetention"], json!(value));
+    }
+    for (index, value) in [preset.graduating_interval, preset.easy_interval]
+        .into_iter()
+        .enumerate()
+    {
+        let Some(value) = value else {
+            continue;
+        };
+        let mut ints = group["new"]["ints"].as_array().cloned().unwrap_or_default();
+        ints.resize(ints.len().max(3), json!(0));
+        ints[index] = json!(value);
+        set(group, &["new", "ints"], Value::Array(ints));
+    }
+    if let Some(value) = &preset.fsrs_params {
+        let Some(key) = fsrs_key(value) else {
+            bail!("preset {} has {} FSRS parameters", name, value.len());
+        };
+        if group.get(key).is_none() {
+            bail!(
+                "options group {} has no {} FSRS parameters, anki uses a different version of FSRS",
+                name,
+                value.len()
+            );
+        }
+        group[key] = json!(value);
+    }
+
+    for (key, value) in &preset.raw {
+        merge(&mut group[key], value);
+    }
+
+    Ok(())
+}
+
+/// Set the option at `path` in `group`, missing objects on the way are created.
+fn set(group: &mut Value, path: &[&str], value: Value) {
+    let mut target = group;
+    for key in path {
+        if !target.is_object() {
+            *target = json!({});
+        }
+        target = &mut target[*key];
+    }
+    *target = value;
+}
+
+/// Merge `value` into `target`, objects are merged recursively.
+fn merge(target: &mut Value, value: &Value) {
+    match (target, value) {
+        (Value::Object(target), Value::Object(value)) => {
+            for (key, value) in value {
+                merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (target, value) => *target = value.clone(),
+    }
+}
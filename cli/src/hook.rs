@@ -0,0 +1,91 @@
+//! Transformation of the notes by an external program, see `hook` in the config.
+//!
+//! The notes are written as a json array to stdin of the program,
+//! which writes the transformed array to stdout.
+//! The output has to be valid notes again, the same as the ones of `typst query`.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::{Help, Result};
+use tracing::{debug, warn};
+
+use crate::metadata::Note;
+
+/// Pipe `notes` of the typst file `path` through `command`.
+///
+/// The program gets the path in `ANKI_TYPST_PATH` and the selected target, if any, in `ANKI_TYPST_TARGET`.
+pub fn run(
+    command: &[String],
+    notes: &[Note],
+    path: &Path,
+    target: Option<&str>,
+) -> Result<Vec<Note>> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("the hook command is empty");
+    };
+    let fmt_cmd = command.join(" ");
+    debug!("running hook {}", fmt_cmd);
+
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .env("ANKI_TYPST_PATH", path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(target) = target {
+        cmd.env("ANKI_TYPST_TARGET", target);
+    }
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| eyre!("can't run hook `{}`: {}", fmt_cmd, e))?;
+
+    let input = serde_json::to_vec(notes)?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // write in another thread, so a program which writes before reading everything can't block
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child
+        .wait_with_output()
+        .with_note(|| eyre!("while running hook `{}`", fmt_cmd))?;
+    let written = writer.join().expect("writing to the hook panicked");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        bail!(
+            "hook `{}` returned error code {}.\nStderr:\n{}",
+            fmt_cmd,
+            output.status,
+            stderr
+        );
+    }
+    written.with_note(|| eyre!("while writing the notes to hook `{}`", fmt_cmd))?;
+    if !stderr.is_empty() {
+        warn!("hook `{}` had non-empty stderr:\n{}", fmt_cmd, stderr);
+    }
+
+    let jd = &mut serde_json::Deserializer::from_slice(&output.stdout);
+    let notes: Vec<Note> = serde_path_to_error::deserialize(jd).map_err(|e| {
+        eyre!(
+            "hook `{}` returned invalid notes at {}: {}",
+            fmt_cmd,
+            e.path(),
+            e.inner()
+        )
+    })?;
+    if let Some((index, _)) = notes
+        .iter()
+        .enumerate()
+        .find(|(_, note)| note.deck.is_empty() || note.model.is_empty())
+    {
+        bail!(
+            "hook `{}` returned a note without deck or model at [{}]",
+            fmt_cmd,
+            index
+        );
+    }
+    debug!("hook returned {} notes", notes.len());
+
+    Ok(notes)
+}
@@ -1,6 +1,105 @@
+mod diagnostic;
+
 use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use indexmap::IndexMap;
+use serde::Deserialize;
 
 pub use cli::{compile, query};
+pub use diagnostic::TypstError;
+
+/// Additional arguments for every typst invocation.
+///
+/// These are applied to both `typst query` and `typst compile`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TypstOptions {
+    /// Project root (`--root`).
+    pub root: Option<PathBuf>,
+    /// Additional font directories (`--font-path`).
+    pub font_paths: Vec<PathBuf>,
+    /// Custom path to local packages (`--package-path`).
+    pub package_path: Option<PathBuf>,
+    /// Additional values for `sys.inputs` (`--input key=value`).
+    pub inputs: IndexMap<String, String>,
+    /// How math in plain fields is written to anki (`--input plain_math=..`).
+    pub plain_math: Option<PlainMath>,
+}
+
+/// How math in fields which aren't rendered as images is written to anki.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlainMath {
+    /// Only the text of the equations, e.g. `a^2 + b^2`
+    #[default]
+    Text,
+    /// Convert the equations to latex for MathJax, e.g. `\(a^{2} + b^{2}\)`.
+    /// Fields with math which can't be converted are rendered as images.
+    Mathjax,
+}
+
+impl TypstOptions {
+    /// Inputs which are set by anki-typst itself and can't be overwritten.
+    const RESERVED_INPUTS: [&'static str; 3] = ["export", "theme", "plain_math"];
+
+    /// Merge `other` into `self`.
+    ///
+    /// Single values of `other` take precedence, lists are extended.
+    pub fn merge(&mut self, other: Self) {
+        if other.root.is_some() {
+            self.root = other.root;
+        }
+        self.font_paths.extend(other.font_paths);
+        if other.package_path.is_some() {
+            self.package_path = other.package_path;
+        }
+        self.inputs.extend(other.inputs);
+        if other.plain_math.is_some() {
+            self.plain_math = other.plain_math;
+        }
+    }
+
+    fn args(&self) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        if let Some(root) = &self.root {
+            args.push(String::from("--root"));
+            args.push(root.to_string_lossy().into_owned());
+        }
+        for font_path in &self.font_paths {
+            args.push(String::from("--font-path"));
+            args.push(font_path.to_string_lossy().into_owned());
+        }
+        if let Some(package_path) = &self.package_path {
+            args.push(String::from("--package-path"));
+            args.push(package_path.to_string_lossy().into_owned());
+        }
+        for (key, value) in &self.inputs {
+            if Self::RESERVED_INPUTS.contains(&key.as_str()) {
+                bail!(
+                    "typst input `{}` is set by anki-typst and can't be overwritten",
+                    key
+                );
+            }
+            args.push(String::from("--input"));
+            args.push(format!("{key}={value}"));
+        }
+        if let Some(plain_math) = self.plain_math {
+            args.push(String::from("--input"));
+            args.push(format!(
+                "plain_math={}",
+                match plain_math {
+                    PlainMath::Text => "text",
+                    PlainMath::Mathjax => "mathjax",
+                }
+            ));
+        }
+
+        Ok(args)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CompileOutput {
@@ -26,54 +125,82 @@ mod cli {
     use color_eyre::Result;
     use tracing::{debug, error, info, warn};
 
-    use crate::interface::{CompileOutput, ThemedCompileOutput};
+    use crate::interface::diagnostic::{Diagnostic, TypstError};
+    use crate::interface::{CompileOutput, ThemedCompileOutput, TypstOptions};
     use crate::metadata::Metadata;
     use crate::Theme;
 
-    fn run_cmd(args: &[&str]) -> Result<Vec<u8>> {
+    fn run_cmd(args: &[String], options: &TypstOptions) -> Result<Vec<u8>> {
+        let mut args = args.to_vec();
+        args.extend(options.args()?);
+        args.extend(["--diagnostic-format", "short"].map(String::from));
+
         let fmt_cmd = format!("typst {}", args.join(" "));
         debug!("running {}", fmt_cmd);
         let mut cmd = Command::new("typst");
-        cmd.args(args);
+        cmd.args(&args);
         let res = cmd
             .output()
             .map_err(|e| eyre!("can't run typst command `{}`: {}", fmt_cmd, e))?;
 
+        let stderr = String::from_utf8_lossy(&res.stderr);
+        let diagnostics = Diagnostic::parse_all(&stderr, options.root.as_deref());
+
         if !res.status.success() {
-            return Err(eyre!(
-                "typst returned error code {} for command {}.\nStdout:\n{}\nStderr:\n{}",
-                res.status,
-                fmt_cmd,
-                String::from_utf8_lossy(&res.stdout),
-                String::from_utf8_lossy(&res.stderr)
-            ));
+            if diagnostics.is_empty() {
+                return Err(eyre!(
+                    "typst returned error code {} for command {}.\nStdout:\n{}\nStderr:\n{}",
+                    res.status,
+                    fmt_cmd,
+                    String::from_utf8_lossy(&res.stdout),
+                    stderr
+                ));
+            }
+            return Err(TypstError {
+                command: fmt_cmd,
+                diagnostics,
+            }
+            .into());
         }
 
-        if !res.stderr.is_empty() {
-            warn!(
-                "typst command {} had non-empty stderr:\n{}",
-                fmt_cmd,
-                String::from_utf8_lossy(&res.stderr)
-            );
+        if diagnostics.is_empty() {
+            if !stderr.is_empty() {
+                warn!(
+                    "typst command {} had non-empty stderr:\n{}",
+                    fmt_cmd, stderr
+                );
+            }
+        } else {
+            for diagnostic in diagnostics {
+                warn!("typst reported\n{}", diagnostic);
+            }
         }
 
         Ok(res.stdout)
     }
 
-    pub fn compile(path: &str, theme: Theme) -> Result<ThemedCompileOutput> {
+    pub fn compile(
+        path: &str,
+        theme: Theme,
+        options: &TypstOptions,
+    ) -> Result<ThemedCompileOutput> {
         match theme {
-            Theme::Dark => compile_inner(path, theme).map(ThemedCompileOutput::Dark),
-            Theme::Light => compile_inner(path, theme).map(ThemedCompileOutput::Light),
+            Theme::Dark => compile_inner(path, theme, options).map(ThemedCompileOutput::Dark),
+            Theme::Light => compile_inner(path, theme, options).map(ThemedCompileOutput::Light),
             Theme::Both => {
-                let light = compile_inner(path, Theme::Light)?;
-                let dark = compile_inner(path, Theme::Dark)?;
+                let light = compile_inner(path, Theme::Light, options)?;
+                let dark = compile_inner(path, Theme::Dark, options)?;
 
                 Ok(ThemedCompileOutput::Both { light, dark })
             }
         }
     }
 
-    pub fn compile_inner(path: &str, theme: Theme) -> Result<CompileOutput> {
+    pub fn compile_inner(
+        path: &str,
+        theme: Theme,
+        options: &TypstOptions,
+    ) -> Result<CompileOutput> {
         let tempdir = tempfile::tempdir().context("create temporary compile output directory")?;
         let output = tempdir.path().join("page{n}.svg");
         let output = output
@@ -87,7 +214,7 @@ mod cli {
                 Theme::Both => bail!("theme both should be handled elsewhere"),
             }
         );
-        let args = &[
+        let args = [
             "compile",
             path,
             output,
@@ -95,8 +222,9 @@ mod cli {
             "export=true",
             "--input",
             &theme_str,
-        ];
-        let stdout = run_cmd(args)?;
+        ]
+        .map(String::from);
+        let stdout = run_cmd(&args, options)?;
 
         if !stdout.is_empty() {
             warn!(
@@ -167,9 +295,10 @@ mod cli {
         Ok(CompileOutput { files: res })
     }
 
-    pub fn query(path: &str) -> Result<Metadata> {
+    pub fn query(path: &str, options: &TypstOptions) -> Result<Metadata> {
         info!("running typst query");
-        let json = run_cmd(&["query", path, "<anki-export>", "--input", "export=true"])?;
+        let args = ["query", path, "<anki-export>", "--input", "export=true"].map(String::from);
+        let json = run_cmd(&args, options)?;
         let jd = &mut serde_json::Deserializer::from_slice(&json);
 
         serde_path_to_error::deserialize(jd).map_err(|e| {
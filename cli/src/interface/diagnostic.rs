@@ -0,0 +1,311 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use regex_lite::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Additional information for the previous diagnostic, e.g. the call site of a function.
+    Help,
+}
+
+impl Severity {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "error" => Some(Self::Error),
+            "warning" => Some(Self::Warning),
+            "help" => Some(Self::Help),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Help => "help",
+        })
+    }
+}
+
+/// Location of a diagnostic in the typst sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number
+    pub column: usize,
+    /// Content of the line, if the file could be read.
+    pub source_line: Option<String>,
+}
+
+/// A diagnostic as reported by typst.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Option<Span>,
+    pub message: String,
+    pub hints: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Parse diagnostics from the output of typst with `--diagnostic-format short`.
+    ///
+    /// Each diagnostic starts with a line `file:line:column: severity: message` or `severity: message`.
+    /// Each step of the trace is a separate `help` diagnostic.
+    /// Typst only prints hints in the long format, but in case they appear,
+    /// lines with hints are attached to the previous diagnostic and other lines continue its message or its last hint.
+    ///
+    /// `root` is used to find the source files if their path is relative to the project root.
+    pub fn parse_all(output: &str, root: Option<&Path>) -> Vec<Self> {
+        let located = Regex::new(r"^(.+):(\d+):(\d+): (error|warning|help): (.*)$")
+            .expect("diagnostic regex must be valid");
+        let unlocated =
+            Regex::new(r"^(error|warning|help): (.*)$").expect("diagnostic regex must be valid");
+
+        let mut diagnostics: Vec<Self> = Vec::new();
+        // whether the previous line was part of a hint
+        let mut in_hint = false;
+        for line in output.lines() {
+            if let Some(captures) = located.captures(line) {
+                let (Some(severity), Ok(line), Ok(column)) = (
+                    Severity::parse(&captures[4]),
+                    captures[2].parse(),
+                    captures[3].parse(),
+                ) else {
+                    continue;
+                };
+                let file = PathBuf::from(&captures[1]);
+                let source_line = read_line(&file, root, line);
+                in_hint = false;
+                diagnostics.push(Self {
+                    severity,
+                    span: Some(Span {
+                        file,
+                        line,
+                        column,
+                        source_line,
+                    }),
+                    message: String::from(&captures[5]),
+                    hints: Vec::new(),
+                });
+            } else if let Some(captures) = unlocated.captures(line) {
+                let Some(severity) = Severity::parse(&captures[1]) else {
+                    continue;
+                };
+                in_hint = false;
+                diagnostics.push(Self {
+                    severity,
+                    span: None,
+                    message: String::from(&captures[2]),
+                    hints: Vec::new(),
+                });
+            } else if let Some(last) = diagnostics.last_mut() {
+                let trimmed = line.trim_start().trim_start_matches("= ");
+                if let Some(hint) = trimmed.strip_prefix("hint: ") {
+                    last.hints.push(String::from(hint));
+                    in_hint = true;
+                } else if line.trim().is_empty() {
+                    in_hint = false;
+                } else {
+                    let (text, line) = match last.hints.last_mut() {
+                        Some(hint) if in_hint => (hint, line.trim_start()),
+                        _ => (&mut last.message, line),
+                    };
+                    text.push('\n');
+                    text.push_str(line);
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    pub const fn is_error(&self) -> bool {
+        matches!(self.severity, Severity::Error)
+    }
+}
+
+fn read_line(file: &Path, root: Option<&Path>, line: usize) -> Option<String> {
+    let path = match root {
+        Some(root) if !file.is_file() => root.join(file),
+        _ => file.to_path_buf(),
+    };
+    let content = std::fs::read_to_string(path).ok()?;
+    content.lines().nth(line.checked_sub(1)?).map(String::from)
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(span) = &self.span {
+            let line_number = span.line.to_string();
+            let padding = " ".repeat(line_number.len());
+            writeln!(
+                f,
+                "{padding}--> {}:{}:{}",
+                span.file.display(),
+                span.line,
+                span.column
+            )?;
+            if let Some(source_line) = &span.source_line {
+                writeln!(f, "{padding} |")?;
+                writeln!(f, "{line_number} | {source_line}")?;
+                // columns are counted in chars, so keep tabs to align the marker
+                let marker_padding: String = source_line
+                    .chars()
+                    .take(span.column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                writeln!(f, "{padding} | {marker_padding}^")?;
+            }
+        }
+        for hint in &self.hints {
+            writeln!(f, "  = hint: {hint}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Typst failed with the contained diagnostics.
+#[derive(Debug)]
+pub struct TypstError {
+    pub command: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TypstError {
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.iter().filter(|d| d.is_error()).count()
+    }
+}
+
+impl Display for TypstError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "typst reported {} error(s) for command {}",
+            self.error_count(),
+            self.command
+        )?;
+        for diagnostic in &self.diagnostics {
+            writeln!(f)?;
+            write!(f, "{diagnostic}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for TypstError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_with_span() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.typ"), "= Title\n#let a = x\n").unwrap();
+        let output = "main.typ:2:10: error: unknown variable: x\n";
+
+        let diagnostics = Diagnostic::parse_all(output, Some(dir.path()));
+
+        assert_eq!(
+            diagnostics,
+            [Diagnostic {
+                severity: Severity::Error,
+                span: Some(Span {
+                    file: PathBuf::from("main.typ"),
+                    line: 2,
+                    column: 10,
+                    source_line: Some(String::from("#let a = x")),
+                }),
+                message: String::from("unknown variable: x"),
+                hints: Vec::new(),
+            }]
+        );
+        assert!(diagnostics[0].is_error());
+        assert!(diagnostics[0]
+            .to_string()
+            .contains("2 | #let a = x\n  |          ^"));
+    }
+
+    #[test]
+    fn warning_without_span() {
+        let output = "warning: unknown font family: foo\n";
+
+        let diagnostics = Diagnostic::parse_all(output, None);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].span, None);
+        assert_eq!(diagnostics[0].message, "unknown font family: foo");
+        assert!(!diagnostics[0].is_error());
+    }
+
+    /// Output of `typst query --diagnostic-format short` for an item without deck.
+    ///
+    /// Warnings come first, each step of the trace is a `help` diagnostic
+    /// and hints are only printed in the long format.
+    const SHORT_OUTPUT: &str = r#"warning: unknown font family: new computer modern
+main.typ:14:1: error: panicked with: "anki-typst: the item `Pythagoras` has no deck"
+@preview/anki:0.3.0/lib.typ:40:4: help: error occurred in this call of function `item`
+main.typ:14:2: help: error occurred in this call of function `theorem`
+"#;
+
+    #[test]
+    fn short_output() {
+        let diagnostics = Diagnostic::parse_all(SHORT_OUTPUT, None);
+
+        let parsed = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let span = diagnostic
+                    .span
+                    .as_ref()
+                    .map(|span| format!("{}:{}:{}", span.file.display(), span.line, span.column));
+                (diagnostic.severity, span, diagnostic.message.as_str())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parsed,
+            [
+                (
+                    Severity::Warning,
+                    None,
+                    "unknown font family: new computer modern"
+                ),
+                (
+                    Severity::Error,
+                    Some(String::from("main.typ:14:1")),
+                    "panicked with: \"anki-typst: the item `Pythagoras` has no deck\""
+                ),
+                (
+                    Severity::Help,
+                    Some(String::from("@preview/anki:0.3.0/lib.typ:40:4")),
+                    "error occurred in this call of function `item`"
+                ),
+                (
+                    Severity::Help,
+                    Some(String::from("main.typ:14:2")),
+                    "error occurred in this call of function `theorem`"
+                ),
+            ]
+        );
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.hints.is_empty()));
+        let error = TypstError {
+            command: String::from("typst query"),
+            diagnostics,
+        };
+        assert_eq!(error.error_count(), 1);
+    }
+}
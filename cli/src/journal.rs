@@ -0,0 +1,823 @@
+//! Local journal of the changes anki-typst made to notes.
+//!
+//! Before a note is changed, its fields, tags and the media files they reference are stored,
+//! so the note can be rolled back later on.
+//! The entries are stored as one json object per line in `journal.jsonl`,
+//! the media files in `media/` next to it.
+//! Only the last runs are kept, see [`Journal::prune`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::{Help, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::api::{self, MediaData, MediaDataInner, NoteInfo, SingleOrMulti};
+use crate::backend::Backend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Created,
+    Updated,
+    RolledBack,
+}
+
+/// Fields and tags of a note in anki.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub fields: IndexMap<String, String>,
+    pub tags: Vec<String>,
+    /// Media files referenced by the fields and the name of their copy in the journal
+    #[serde(default)]
+    pub media: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    fn new(info: NoteInfo) -> Self {
+        let mut fields = info.fields.into_iter().collect::<Vec<_>>();
+        fields.sort_by_key(|(_, field)| field.order);
+        Self {
+            fields: fields
+                .into_iter()
+                .map(|(name, field)| (name, field.value))
+                .collect(),
+            tags: info.tags,
+            media: BTreeMap::new(),
+        }
+    }
+
+    /// Names of the fields and `tags` if they differ from `other`.
+    fn changes(&self, other: &Snapshot) -> Vec<&str> {
+        let mut changes = self
+            .fields
+            .iter()
+            .filter(|(name, value)| other.fields.get(*name) != Some(value))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        if self.tags != other.tags {
+            changes.push("tags");
+        }
+        changes
+    }
+}
+
+/// A single change of a note.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Start of the run which made the change, identifies the run
+    pub run: String,
+    pub action: Action,
+    pub note_id: usize,
+    pub typst_id: Option<String>,
+    pub deck: String,
+    pub model: String,
+    /// `None` if the note was created
+    pub before: Option<Snapshot>,
+    pub after: Snapshot,
+}
+
+/// A change which is about to be recorded, see [`Journal::record`].
+#[derive(Debug)]
+pub struct Change {
+    pub action: Action,
+    pub note_id: usize,
+    pub typst_id: Option<String>,
+    pub deck: String,
+    pub model: String,
+    pub before: Option<Snapshot>,
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    dir: PathBuf,
+    run: String,
+}
+
+impl Journal {
+    /// Journal in `dir` for a new run.
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            run: chrono::Local::now()
+                .format("%Y-%m-%dT%H:%M:%S%.3f")
+                .to_string(),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("journal.jsonl")
+    }
+
+    fn media_dir(&self) -> PathBuf {
+        self.dir.join("media")
+    }
+
+    /// All entries, the oldest first.
+    pub fn load(&self) -> Result<Vec<Entry>> {
+        let path = self.path();
+        if !path.is_file() {
+            return Ok(Vec::new());
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_note(|| eyre!("while reading the journal {}", path.display()))?;
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .with_note(|| eyre!("in line {} of the journal {}", index + 1, path.display()))
+            })
+            .collect()
+    }
+
+    /// Entries of the note with the typst or anki id `note`, the oldest first.
+    ///
+    /// Fails if the typst id belongs to more than one anki note.
+    pub fn history(&self, note: &str) -> Result<Vec<Entry>> {
+        let entries = self
+            .load()?
+            .into_iter()
+            .filter(|entry| {
+                entry.typst_id.as_deref() == Some(note) || entry.note_id.to_string() == note
+            })
+            .collect::<Vec<_>>();
+        let mut note_ids = entries
+            .iter()
+            .map(|entry| entry.note_id)
+            .collect::<Vec<_>>();
+        note_ids.sort_unstable();
+        note_ids.dedup();
+        if note_ids.len() > 1 {
+            bail!(
+                "{} belongs to the notes {}, use one of the anki ids instead",
+                note,
+                note_ids
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(entries)
+    }
+
+    /// The fields and tags of each note as anki-typst wrote them last, by anki id.
+    pub fn last_written(&self) -> Result<HashMap<usize, Snapshot>> {
+        Ok(self
+            .load()?
+            .into_iter()
+            .map(|entry| (entry.note_id, entry.after))
+            .collect())
+    }
+
+    /// Get the current fields and tags of the notes and store the media files they reference.
+    ///
+    /// Media files which are in the `known` snapshot of a note are already stored,
+    /// so only the files whose names changed are retrieved from anki.
+    pub fn snapshots(
+        &self,
+        backend: &dyn Backend,
+        ids: &[usize],
+        known: &HashMap<usize, Snapshot>,
+    ) -> Result<HashMap<usize, Snapshot>> {
+        let mut snapshots = current(backend, ids)?;
+        for (id, snapshot) in &mut snapshots {
+            let known = known.get(id).map(|known| &known.media);
+            self.save_media(backend, snapshot, known)?;
+        }
+        Ok(snapshots)
+    }
+
+    fn save_media(
+        &self,
+        backend: &dyn Backend,
+        snapshot: &mut Snapshot,
+        known: Option<&BTreeMap<String, String>>,
+    ) -> Result<()> {
+        let filenames = snapshot
+            .fields
+            .values()
+            .flat_map(|value| media_references(value))
+            .collect::<Vec<_>>();
+        for filename in filenames {
+            if snapshot.media.contains_key(&filename) {
+                continue;
+            }
+            let stored = known
+                .and_then(|known| known.get(&filename))
+                .filter(|stored| self.media_dir().join(stored).is_file());
+            if let Some(stored) = stored {
+                snapshot.media.insert(filename, stored.clone());
+                continue;
+            }
+            let Some(data) = backend.retrieve_media_file(&filename)? else {
+                debug!("media file {} does not exist", filename);
+                continue;
+            };
+            let data = base64::engine::general_purpose::STANDARD.decode(data)?;
+            // the same name may be used for different files over time
+            let stored = format!("{:016x}_{filename}", fasthash::metro::hash64(&data));
+            let path = self.media_dir().join(&stored);
+            if !path.is_file() {
+                std::fs::create_dir_all(self.media_dir())?;
+                std::fs::write(&path, data)
+                    .with_note(|| eyre!("while writing {}", path.display()))?;
+            }
+            snapshot.media.insert(filename, stored);
+        }
+        Ok(())
+    }
+
+    /// Get the notes of `changes` after they were changed and append them to the journal.
+    ///
+    /// Returns the recorded values of the notes by anki id.
+    pub fn record(
+        &self,
+        backend: &dyn Backend,
+        changes: Vec<Change>,
+    ) -> Result<HashMap<usize, Snapshot>> {
+        if changes.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let ids = changes
+            .iter()
+            .map(|change| change.note_id)
+            .collect::<Vec<_>>();
+        let before = changes
+            .iter()
+            .filter_map(|change| Some((change.note_id, change.before.clone()?)))
+            .collect();
+        let mut after = self.snapshots(backend, &ids, &before)?;
+
+        std::fs::create_dir_all(&self.dir)
+            .with_note(|| eyre!("while creating directory {}", self.dir.display()))?;
+        let path = self.path();
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_note(|| eyre!("while opening the journal {}", path.display()))?;
+        let mut written = HashMap::with_capacity(changes.len());
+        for change in changes {
+            let Some(after) = after.remove(&change.note_id) else {
+                warn!("note {} was not found after changing it", change.note_id);
+                continue;
+            };
+            let entry = Entry {
+                run: self.run.clone(),
+                action: change.action,
+                note_id: change.note_id,
+                typst_id: change.typst_id,
+                deck: change.deck,
+                model: change.model,
+                before: change.before,
+                after,
+            };
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+            written.insert(entry.note_id, entry.after);
+        }
+        debug!("recorded changes in the journal {}", path.display());
+
+        Ok(written)
+    }
+
+    /// Remove the entries of all but the last `runs` runs and the media files which only they reference.
+    ///
+    /// The last entry of each note is always kept, so the values written last are still known.
+    pub fn prune(&self, runs: usize) -> Result<()> {
+        let entries = self.load()?;
+        let mut seen = HashSet::new();
+        let all_runs = entries
+            .iter()
+            .map(|entry| entry.run.as_str())
+            .filter(|run| seen.insert(*run))
+            .collect::<Vec<_>>();
+        if all_runs.len() <= runs {
+            return Ok(());
+        }
+        let kept_runs = all_runs[all_runs.len() - runs..]
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>();
+        let last_entries = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.note_id, index))
+            .collect::<HashMap<_, _>>();
+        let kept = entries
+            .iter()
+            .enumerate()
+            .filter(|(index, entry)| {
+                kept_runs.contains(entry.run.as_str())
+                    || last_entries.get(&entry.note_id) == Some(index)
+            })
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>();
+
+        let mut content = String::new();
+        for entry in &kept {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        // the journal is replaced at once, so it isn't lost if writing fails
+        let path = self.path();
+        let temporary = path.with_extension("jsonl.tmp");
+        std::fs::write(&temporary, content)
+            .with_note(|| eyre!("while writing {}", temporary.display()))?;
+        std::fs::rename(&temporary, &path)
+            .with_note(|| eyre!("while replacing the journal {}", path.display()))?;
+
+        let referenced = kept
+            .iter()
+            .flat_map(|entry| entry.before.iter().chain([&entry.after]))
+            .flat_map(|snapshot| snapshot.media.values())
+            .map(String::as_str)
+            .collect::<HashSet<_>>();
+        let media_dir = self.media_dir();
+        if media_dir.is_dir() {
+            for file in std::fs::read_dir(&media_dir)? {
+                let path = file?.path();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if !referenced.contains(name.as_ref()) {
+                    std::fs::remove_file(&path)
+                        .with_note(|| eyre!("while removing {}", path.display()))?;
+                }
+            }
+        }
+        debug!(
+            "removed {} entries of old runs from the journal",
+            entries.len() - kept.len()
+        );
+        Ok(())
+    }
+
+    /// Restore the note of `entry` to `target`, which is recorded as a new change.
+    fn restore(&self, backend: &dyn Backend, entry: &Entry, target: &Snapshot) -> Result<()> {
+        let before = self
+            .snapshots(backend, &[entry.note_id], &HashMap::new())?
+            .remove(&entry.note_id)
+            .ok_or_else(|| eyre!("note {} does not exist anymore", entry.note_id))?;
+
+        for (filename, stored) in &target.media {
+            let path = self.media_dir().join(stored);
+            let data = std::fs::read(&path)
+                .with_note(|| eyre!("while reading {} from the journal", path.display()))?;
+            backend.store_media_file(&MediaData {
+                filename: filename.clone(),
+                inner: MediaDataInner::Data(base64::engine::general_purpose::STANDARD.encode(data)),
+                delete_existing: true,
+                fields: Vec::new(),
+            })?;
+        }
+        backend.update_note(
+            entry.note_id,
+            &api::Note {
+                deck_name: entry.deck.clone(),
+                model_name: entry.model.clone(),
+                fields: target.fields.clone().into_iter().collect(),
+                tags: target.tags.clone(),
+                audio: SingleOrMulti::Multi(Vec::new()),
+                video: SingleOrMulti::Multi(Vec::new()),
+                picture: SingleOrMulti::Multi(Vec::new()),
+            },
+        )?;
+
+        self.record(
+            backend,
+            vec![Change {
+                action: Action::RolledBack,
+                note_id: entry.note_id,
+                typst_id: entry.typst_id.clone(),
+                deck: entry.deck.clone(),
+                model: entry.model.clone(),
+                before: Some(before),
+            }],
+        )?;
+        Ok(())
+    }
+
+    /// Restore the note with the typst or anki id `note`.
+    ///
+    /// With `revision` the note gets the values written by this revision,
+    /// otherwise the last change is undone.
+    pub fn rollback_note(
+        &self,
+        backend: &dyn Backend,
+        note: &str,
+        revision: Option<usize>,
+    ) -> Result<()> {
+        let history = self.history(note)?;
+        let Some(last) = history.last() else {
+            bail!("there are no changes of note {} in the journal", note);
+        };
+        let target = match revision {
+            Some(revision) => {
+                let Some(entry) = revision.checked_sub(1).and_then(|index| history.get(index))
+                else {
+                    bail!(
+                        "note {} has only the revisions 1 to {}",
+                        note,
+                        history.len()
+                    );
+                };
+                &entry.after
+            }
+            None => match &last.before {
+                Some(before) => before,
+                None => bail!(
+                    "note {} was created by its last change, delete it in anki instead",
+                    note
+                ),
+            },
+        };
+
+        self.restore(backend, last, target)?;
+        info!("rolled back note {}", note);
+        Ok(())
+    }
+
+    /// Undo all changes of the run `run`.
+    pub fn rollback_run(&self, backend: &dyn Backend, run: &str) -> Result<()> {
+        let entries = self
+            .load()?
+            .into_iter()
+            .filter(|entry| entry.run == run)
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            bail!("there is no run {} in the journal", run);
+        }
+
+        let mut restored = 0;
+        for entry in entries.iter().rev() {
+            let Some(before) = &entry.before else {
+                warn!(
+                    "note {} was created by run {}, delete it in anki instead",
+                    entry.note_id, run
+                );
+                continue;
+            };
+            self.restore(backend, entry, before)?;
+            restored += 1;
+        }
+        info!("rolled back {} notes of run {}", restored, run);
+        Ok(())
+    }
+}
+
+/// The current fields and tags of the notes, without their media files.
+pub fn current(backend: &dyn Backend, ids: &[usize]) -> Result<HashMap<usize, Snapshot>> {
+    let mut snapshots = HashMap::with_capacity(ids.len());
+    for ids in ids.chunks(400) {
+        for info in backend.notes_info(ids)? {
+            snapshots.insert(info.note_id, Snapshot::new(info));
+        }
+    }
+    Ok(snapshots)
+}
+
+fn action_name(action: Action) -> &'static str {
+    match action {
+        Action::Created => "created",
+        Action::Updated => "updated",
+        Action::RolledBack => "rolled back",
+    }
+}
+
+/// Print the revisions of a single note, see [`Journal::history`].
+pub fn print_history(entries: &[Entry]) {
+    let Some(last) = entries.last() else {
+        println!("no changes in the journal");
+        return;
+    };
+    println!(
+        "note {} ({}) in deck {}",
+        last.note_id,
+        last.typst_id.as_deref().unwrap_or("<no id>"),
+        last.deck
+    );
+    for (index, entry) in entries.iter().enumerate() {
+        let changes = match &entry.before {
+            Some(before) => entry.after.changes(before).join(", "),
+            None => String::new(),
+        };
+        println!(
+            "  revision {:>3}  {}  {:<11}  {}",
+            index + 1,
+            entry.run,
+            action_name(entry.action),
+            changes
+        );
+    }
+}
+
+/// Print all runs in the journal with the number of changed notes.
+pub fn print_runs(entries: &[Entry]) {
+    let mut runs: Vec<(&str, [usize; 3])> = Vec::new();
+    for entry in entries {
+        if runs.last().map(|(run, _)| *run) != Some(entry.run.as_str()) {
+            runs.push((&entry.run, [0; 3]));
+        }
+        let counts = &mut runs.last_mut().expect("was just pushed").1;
+        counts[entry.action as usize] += 1;
+    }
+    if runs.is_empty() {
+        println!("no changes in the journal");
+    }
+    for (run, [created, updated, rolled_back]) in runs {
+        println!("{run}  created {created:>4}  updated {updated:>4}  rolled back {rolled_back:>4}");
+    }
+}
+
+/// Names of the media files referenced in `html`, by `src` attributes and `[sound:..]`.
+fn media_references(html: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    for (start, end) in [("src=\"", "\""), ("src='", "'"), ("[sound:", "]")] {
+        let mut rest = html;
+        while let Some(index) = rest.find(start) {
+            rest = &rest[index + start.len()..];
+            let Some(len) = rest.find(end) else {
+                break;
+            };
+            let filename = &rest[..len];
+            // only local files
+            if !filename.is_empty() && !filename.contains(['/', '\\', ':']) {
+                references.push(filename.to_owned());
+            }
+            rest = &rest[len..];
+        }
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::CreateModelData;
+    use crate::backend::InMemory;
+
+    fn backend() -> InMemory {
+        let backend = InMemory::default();
+        backend
+            .create_model(&CreateModelData {
+                model_name: String::from("Basic"),
+                in_order_fields: vec![String::from("Front"), String::from("Back")],
+                css: String::new(),
+                is_cloze: false,
+                card_templates: vec![HashMap::from([
+                    (String::from("Front"), String::from("{{Front}}")),
+                    (String::from("Back"), String::from("{{Back}}")),
+                ])],
+            })
+            .unwrap();
+        backend
+    }
+
+    fn journal(dir: &Path, run: &str) -> Journal {
+        Journal {
+            dir: dir.to_path_buf(),
+            run: String::from(run),
+        }
+    }
+
+    fn note(front: &str) -> api::Note {
+        api::Note {
+            deck_name: String::from("Default"),
+            model_name: String::from("Basic"),
+            fields: HashMap::from([
+                (String::from("Front"), String::from(front)),
+                (String::from("Back"), String::from("back")),
+            ]),
+            tags: Vec::new(),
+            audio: SingleOrMulti::default(),
+            video: SingleOrMulti::default(),
+            picture: SingleOrMulti::default(),
+        }
+    }
+
+    fn store(backend: &InMemory, filename: &str, content: &str) {
+        backend
+            .store_media_file(&MediaData {
+                filename: String::from(filename),
+                inner: MediaDataInner::Data(
+                    base64::engine::general_purpose::STANDARD.encode(content),
+                ),
+                delete_existing: true,
+                fields: Vec::new(),
+            })
+            .unwrap();
+    }
+
+    fn change(action: Action, note_id: usize, typst_id: &str, before: Option<Snapshot>) -> Change {
+        Change {
+            action,
+            note_id,
+            typst_id: Some(String::from(typst_id)),
+            deck: String::from("Default"),
+            model: String::from("Basic"),
+            before,
+        }
+    }
+
+    /// Create notes with the typst ids and fronts `notes` in the run `run`.
+    fn create(backend: &InMemory, journal: &Journal, notes: &[(&str, &str)]) -> Vec<usize> {
+        let ids = backend
+            .add_notes(
+                &notes
+                    .iter()
+                    .map(|(_, front)| note(front))
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap()
+            .into_iter()
+            .map(Option::unwrap)
+            .collect::<Vec<_>>();
+        let changes = ids
+            .iter()
+            .zip(notes)
+            .map(|(id, (typst_id, _))| change(Action::Created, *id, typst_id, None))
+            .collect();
+        journal.record(backend, changes).unwrap();
+        ids
+    }
+
+    fn update(backend: &InMemory, journal: &Journal, id: usize, typst_id: &str, front: &str) {
+        let before = journal
+            .snapshots(backend, &[id], &journal.last_written().unwrap())
+            .unwrap()
+            .remove(&id);
+        backend.update_note(id, &note(front)).unwrap();
+        let changes = vec![change(Action::Updated, id, typst_id, before)];
+        journal.record(backend, changes).unwrap();
+    }
+
+    fn front(backend: &InMemory, id: usize) -> String {
+        backend.notes_info(&[id]).unwrap()[0].fields["Front"]
+            .value
+            .clone()
+    }
+
+    fn media(dir: &Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(dir.join("media"))
+            .unwrap()
+            .map(|file| {
+                let name = file.unwrap().file_name().to_string_lossy().into_owned();
+                // without the hash of the content
+                name.split_once('_').unwrap().1.to_owned()
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn prune_old_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend();
+        store(&backend, "a1.png", "a1");
+        store(&backend, "a2.png", "a2");
+        store(&backend, "b.png", "b");
+        let ids = create(
+            &backend,
+            &journal(dir.path(), "1"),
+            &[("A", "<img src=\"a1.png\">"), ("B", "<img src=\"b.png\">")],
+        );
+        update(
+            &backend,
+            &journal(dir.path(), "2"),
+            ids[0],
+            "A",
+            "<img src=\"a2.png\">",
+        );
+        update(&backend, &journal(dir.path(), "3"), ids[0], "A", "plain");
+        assert_eq!(media(dir.path()), ["a1.png", "a2.png", "b.png"]);
+
+        let journal = journal(dir.path(), "4");
+        journal.prune(1).unwrap();
+        let entries = journal.load().unwrap();
+        let kept = entries
+            .iter()
+            .map(|entry| (entry.run.as_str(), entry.typst_id.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        // the last entry of B is kept although its run is pruned
+        assert_eq!(kept, [("1", "B"), ("3", "A")]);
+        // a2.png is still referenced by the state before run 3
+        assert_eq!(media(dir.path()), ["a2.png", "b.png"]);
+
+        // nothing to prune
+        journal.prune(2).unwrap();
+        assert_eq!(journal.load().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rollback_single_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend();
+        store(&backend, "a.png", "first");
+        let id = create(
+            &backend,
+            &journal(dir.path(), "1"),
+            &[("A", "<img src=\"a.png\">")],
+        )[0];
+        // the file is replaced in anki with the update
+        store(&backend, "a.png", "second");
+        update(
+            &backend,
+            &journal(dir.path(), "2"),
+            id,
+            "A",
+            "<img src=\"a.png\"> 2",
+        );
+        update(&backend, &journal(dir.path(), "3"), id, "A", "third");
+
+        // undo the last change
+        let journal = journal(dir.path(), "4");
+        journal.rollback_note(&backend, "A", None).unwrap();
+        assert_eq!(front(&backend, id), "<img src=\"a.png\"> 2");
+        // the values written by the first revision, including its media
+        journal
+            .rollback_note(&backend, &id.to_string(), Some(1))
+            .unwrap();
+        assert_eq!(front(&backend, id), "<img src=\"a.png\">");
+        let data = backend.retrieve_media_file("a.png").unwrap().unwrap();
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .unwrap(),
+            b"first"
+        );
+
+        let history = journal.history("A").unwrap();
+        let actions = history.iter().map(|entry| entry.action).collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            [
+                Action::Created,
+                Action::Updated,
+                Action::Updated,
+                Action::RolledBack,
+                Action::RolledBack
+            ]
+        );
+        let e = journal.rollback_note(&backend, "A", Some(6)).unwrap_err();
+        assert!(
+            format!("{e:?}").contains("only the revisions 1 to 5"),
+            "{e:?}"
+        );
+    }
+
+    #[test]
+    fn rollback_whole_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend();
+        let ids = create(
+            &backend,
+            &journal(dir.path(), "1"),
+            &[("A", "a"), ("B", "b")],
+        );
+        let run = journal(dir.path(), "2");
+        update(&backend, &run, ids[0], "A", "a 2");
+        let created = create(&backend, &run, &[("C", "c")])[0];
+        update(&backend, &journal(dir.path(), "3"), ids[1], "B", "b 2");
+
+        let journal = journal(dir.path(), "4");
+        journal.rollback_run(&backend, "2").unwrap();
+        assert_eq!(front(&backend, ids[0]), "a");
+        // created notes aren't deleted, later runs aren't undone
+        assert_eq!(front(&backend, created), "c");
+        assert_eq!(front(&backend, ids[1]), "b 2");
+        let rolled_back = journal
+            .load()
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.run == "4")
+            .map(|entry| (entry.action, entry.note_id))
+            .collect::<Vec<_>>();
+        assert_eq!(rolled_back, [(Action::RolledBack, ids[0])]);
+
+        assert!(journal.rollback_run(&backend, "5").is_err());
+    }
+
+    #[test]
+    fn history_of_typst_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend();
+        let first = create(&backend, &journal(dir.path(), "1"), &[("A", "a")])[0];
+        let second = create(&backend, &journal(dir.path(), "2"), &[("A", "a 2")])[0];
+        update(&backend, &journal(dir.path(), "3"), first, "A", "a 3");
+
+        let journal = journal(dir.path(), "4");
+        let e = journal.history("A").unwrap_err();
+        assert!(
+            format!("{e:?}").contains(&format!("belongs to the notes {first}, {second}, use")),
+            "{e:?}"
+        );
+        assert_eq!(journal.history(&first.to_string()).unwrap().len(), 2);
+    }
+}
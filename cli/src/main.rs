@@ -1,27 +1,46 @@
+use base64::Engine;
 use clap::Parser;
 use color_eyre::eyre::{bail, eyre};
 use color_eyre::{Help, Result};
 use notify::{Event, EventKind, RecursiveMode, Watcher};
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
-use tracing::{debug, error, info, Level};
+use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
 use crate::api::{
-    add_notes, create_model, get_model_field_names_multi, CreateModelData, MediaData,
-    MediaDataInner, SingleOrMulti,
+    AnkiConnect, CreateModelData, MediaData, MediaDataInner, NoteInfo, SingleOrMulti,
 };
-use crate::interface::{compile, query, CompileOutput, ThemedCompileOutput};
-use crate::metadata::{Field, Note};
-use api::{cards_info, find_notes, get_deck_names, get_model_names, notes_info, sync};
-use config::Config;
+use crate::backend::{Backend, InMemory, Overlay};
+use crate::interface::{
+    compile, query, CompileOutput, PlainMath, ThemedCompileOutput, TypstError, TypstOptions,
+};
+use crate::journal::{Action, Change, Journal, Snapshot};
+use crate::metadata::{Field, MediaKind, Note};
+use backend::search;
+use config::{AnkiEditPolicy, Config, FetchScope, RevisionPolicy, TextLayer};
+use source::{Location, SourceIndex};
 
+mod anki_edits;
 mod api;
+mod backend;
 mod config;
+mod deck_options;
+mod hook;
 mod interface;
+mod journal;
 mod metadata;
+mod preview;
+mod rewrite;
+mod source;
+mod stats;
+mod stub;
+mod tags;
+mod template;
 
 const BIN_NAME: &str = "anki-typst";
+const DEFAULT_MODEL: &str = "anki-typst";
 
 #[derive(Debug, Clone)]
 pub struct NoteWithInfo {
@@ -31,39 +50,6 @@ pub struct NoteWithInfo {
     pub question: Option<String>,
 }
 
-impl PartialEq<Note> for NoteWithInfo {
-    fn eq(&self, oinner: &Note) -> bool {
-        let inner = &self.note;
-        let matching =
-            inner.deck == oinner.deck && inner.model == oinner.model && inner.tags == oinner.tags;
-
-        let get_fields = |val: &Note| {
-            val.fields
-                .iter()
-                .filter(|(_, v)| match v {
-                    Field::Raw(v) | Field::Plain { plain: v } => !v.is_empty(),
-                    Field::Content { .. } | Field::Empty => false,
-                })
-                .map(|(k, v)| (String::from(k), v.to_string()))
-                .collect::<HashSet<_>>()
-        };
-        let a_fields = get_fields(inner);
-        let b_fields = get_fields(oinner);
-        let fields_match = a_fields == b_fields;
-
-        let same = matching && fields_match;
-
-        if let (Some(s_id), Some(o_id)) = (&inner.id, &oinner.id) {
-            if same && s_id != o_id {
-                error!("Id differs {} != {} but contents are the same (deck {}, model {}, fields {:?}, tags {:?})",
-                    s_id, o_id, inner.deck, inner.model, inner.fields, inner.tags);
-            }
-        }
-
-        same
-    }
-}
-
 #[derive(Debug, PartialEq)]
 struct Model {
     field_names: Vec<String>,
@@ -74,48 +60,172 @@ struct State {
     deck_names: Vec<String>,
     models: HashMap<String, Model>,
     added_notes: Vec<NoteWithInfo>,
+    /// Index into `added_notes` by deck and typst id
+    notes_by_id: HashMap<(String, String), usize>,
+    /// Index into `added_notes` by [`Note::fingerprint`]
+    notes_by_fingerprint: HashMap<u64, usize>,
+    /// Index into `added_notes` by anki id
+    note_ids: HashMap<usize, usize>,
+    /// Decks, models or scopes whose notes are already fetched
+    fetched: HashSet<String>,
     last_hashes: HashMap<PathBuf, u64>,
+    /// Values anki-typst wrote last by anki id, loaded from the journal on first use
+    last_written: Option<HashMap<usize, Snapshot>>,
 }
 
 impl State {
-    fn load_models() -> Result<HashMap<String, Model>> {
-        let model_names = get_model_names()?.0;
-        get_model_field_names_multi(model_names.iter().map(String::as_str))?
+    fn load_models(backend: &dyn Backend) -> Result<HashMap<String, Model>> {
+        let model_names = backend.model_names()?;
+        backend
+            .model_field_names(&model_names)?
             .into_iter()
             .zip(model_names)
-            .map(|(field_names, name)| {
-                Ok((
-                    name,
-                    Model {
-                        field_names: field_names.0,
-                    },
-                ))
-            })
+            .map(|(field_names, name)| Ok((name, Model { field_names })))
             .collect()
     }
 
-    fn new() -> Result<Self> {
+    fn new(backend: &dyn Backend) -> Result<Self> {
         debug!("loading state");
-        let models = Self::load_models()?;
+        let models = Self::load_models(backend)?;
         Ok(Self {
-            deck_names: get_deck_names()?.0,
+            deck_names: backend.deck_names()?,
             models,
-            added_notes: get_notes("*")?,
+            added_notes: Vec::new(),
+            notes_by_id: HashMap::default(),
+            notes_by_fingerprint: HashMap::default(),
+            note_ids: HashMap::default(),
+            fetched: HashSet::default(),
             last_hashes: HashMap::default(),
+            last_written: None,
         })
     }
 
+    /// Fetch all notes from anki which could match one of `notes`.
+    ///
+    /// Notes are only fetched once, so this is cheap to call for every change.
+    fn fetch_notes<'a>(
+        &mut self,
+        backend: &dyn Backend,
+        config: &Config,
+        scope: FetchScope,
+        notes: impl IntoIterator<Item = &'a Note>,
+    ) -> Result<()> {
+        let notes = notes.into_iter();
+        let (scopes, queries): (Vec<_>, Vec<_>) = match scope {
+            FetchScope::All => vec![(String::from("*"), (String::from("*"), None))],
+            FetchScope::Generated => vec![(
+                format!("tag:{}", tags::GENERATED),
+                (search::term("tag", tags::GENERATED), None),
+            )],
+            FetchScope::Decks => notes
+                .map(|note| {
+                    let deck = &note.deck;
+                    // subdecks are fetched separately, so the deck of the notes is known
+                    let query = format!(
+                        "{} -\"deck:{}::*\"",
+                        search::term("deck", deck),
+                        search::escape(deck)
+                    );
+                    (format!("deck:{deck}"), (query, Some(deck.clone())))
+                })
+                .collect(),
+            FetchScope::Models => notes
+                .map(|note| {
+                    let query = search::term("note", &note.model);
+                    (format!("note:{}", note.model), (query, None))
+                })
+                .collect(),
+        }
+        .into_iter()
+        .filter(|(scope, _)| !self.fetched.contains(scope))
+        .collect::<HashMap<_, _>>()
+        .into_iter()
+        .unzip();
+
+        for (query, deck) in queries {
+            debug!("fetching notes for {}", query);
+            for note in get_notes(backend, config, &query, deck.as_deref())? {
+                self.add_note(note);
+            }
+        }
+        self.fetched.extend(scopes);
+
+        Ok(())
+    }
+
+    /// Remember `note`, replacing a note with the same anki id.
+    fn add_note(&mut self, note: NoteWithInfo) {
+        let id_key = |note: &Note| note.id.clone().map(|id| (note.deck.clone(), id));
+        let index = match note.id.and_then(|id| self.note_ids.get(&id)) {
+            Some(&index) => {
+                let old = &self.added_notes[index].note;
+                if let Some(key) = id_key(old) {
+                    if self.notes_by_id.get(&key) == Some(&index) {
+                        self.notes_by_id.remove(&key);
+                    }
+                }
+                let fingerprint = old.fingerprint();
+                if self.notes_by_fingerprint.get(&fingerprint) == Some(&index) {
+                    self.notes_by_fingerprint.remove(&fingerprint);
+                }
+                index
+            }
+            None => self.added_notes.len(),
+        };
+        if let Some(id) = note.id {
+            self.note_ids.insert(id, index);
+        }
+        if let Some(key) = id_key(&note.note) {
+            self.notes_by_id.entry(key).or_insert(index);
+        }
+        self.notes_by_fingerprint
+            .entry(note.note.fingerprint())
+            .or_insert(index);
+        if index == self.added_notes.len() {
+            self.added_notes.push(note);
+        } else {
+            self.added_notes[index] = note;
+        }
+    }
+
+    /// Find an existing note with the same deck and typst id as `note`.
+    fn find_note_by_id(&self, note: &Note) -> Option<&NoteWithInfo> {
+        let id = note.id.as_ref()?;
+        let index = self.notes_by_id.get(&(note.deck.clone(), id.clone()))?;
+        Some(&self.added_notes[*index])
+    }
+
+    /// Find an existing note with the same content as `note`.
+    fn find_note(&self, note: &Note) -> Option<&NoteWithInfo> {
+        let found = &self.added_notes[*self.notes_by_fingerprint.get(&note.fingerprint())?];
+
+        if let (Some(s_id), Some(o_id)) = (&found.note.id, &note.id) {
+            if s_id != o_id {
+                error!("Id differs {} != {} but contents are the same (deck {}, model {}, fields {:?}, tags {:?})",
+                    s_id, o_id, note.deck, note.model, note.fields, note.tags);
+            }
+        }
+
+        Some(found)
+    }
+
     // TODO reload state less often
-    fn reload(&mut self) -> Result<()> {
+    fn reload(&mut self, backend: &dyn Backend) -> Result<()> {
         debug!("reloading state");
-        self.deck_names = get_deck_names()?.0;
-        self.models = Self::load_models()?;
+        self.deck_names = backend.deck_names()?;
+        self.models = Self::load_models(backend)?;
 
         Ok(())
     }
 }
 
-fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateArgs) -> Result<()> {
+fn update_change(
+    state: &mut State,
+    backend: &dyn Backend,
+    config: &Config,
+    path: &Path,
+    args: &CreateArgs,
+) -> Result<()> {
     let path_str = path.to_string_lossy();
     if config.is_ignored(&path_str) {
         return Ok(());
@@ -129,7 +239,7 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
             .with_note(|| eyre!("while collecting children of {}", path.display()))?;
         for read_dir in children {
             let new_path = read_dir?.path();
-            update_change(state, config, &new_path, args)?;
+            update_change(state, backend, config, &new_path, args)?;
         }
 
         return Ok(());
@@ -145,24 +255,95 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
             return Ok(());
         }
     }
-    state.last_hashes.insert(path.into(), content_hash);
     info!("updating changes from {}", path.display());
-    state.reload()?;
+    state.reload(backend)?;
 
     debug!("getting metadata for file {}", path.display());
-    let metadata = query(&path_str)?;
+    let notes = query_notes(path, config)?;
+    let sources = load_sources(path, config);
+    let media_base = SourceIndex::base(path, config.typst.root.as_deref());
     debug!("compiling file {}", path.display());
-    let output = compile(&path_str, args.theme)?;
+    let theme = args.theme.or(config.target_theme()).unwrap_or(Theme::Both);
+    let output = compile(&path_str, theme, &config.typst)?;
     debug!("finished compiling file");
 
+    let files = Files {
+        sources: sources.as_ref(),
+        media_base: &media_base,
+        output: &output,
+    };
+    if !sync_notes(state, backend, config, notes, &files, args)? {
+        return Ok(());
+    }
+
+    // only remember the file after it was processed, so it is retried after a failure
+    state.last_hashes.insert(path.into(), content_hash);
+
+    Ok(())
+}
+
+/// Read the typst sources of `path` to find the location of notes, if it is stored.
+///
+/// The location is only a hint, so notes are written without it if the sources can't be read.
+fn load_sources(path: &Path, config: &Config) -> Option<SourceIndex> {
+    if !config.stores_sources() {
+        return None;
+    }
+    SourceIndex::load(path, config.typst.root.as_deref())
+        .map_err(|e| {
+            warn!(
+                "notes are written without their location in the sources: {:?}",
+                e
+            )
+        })
+        .ok()
+}
+
+/// Everything of the typst file which belongs to its notes, apart from the notes themselves.
+struct Files<'a> {
+    /// `None` if the location of the notes isn't stored
+    sources: Option<&'a SourceIndex>,
+    /// Directory the paths of media files are relative to
+    media_base: &'a Path,
+    output: &'a ThemedCompileOutput,
+}
+
+/// Create the new `notes` in anki and update the changed ones.
+///
+/// Returns `false` if a note is invalid, the notes may be written partially then.
+fn sync_notes(
+    state: &mut State,
+    backend: &dyn Backend,
+    config: &Config,
+    notes: Vec<Note>,
+    files: &Files,
+    args: &CreateArgs,
+) -> Result<bool> {
+    let scope = if args.full_scan {
+        FetchScope::All
+    } else {
+        config.fetch_scope
+    };
+    state.fetch_notes(backend, config, scope, &notes)?;
+    add_missing_fields(state, backend, config, &notes)?;
+
+    let journal = config.journal_dir.as_deref().map(Journal::new);
     let mut note_decks: HashMap<String, (Vec<_>, Vec<_>)> = HashMap::new();
+    let mut updated_notes = Vec::new();
+    // anki id and whether the cards should be suspended, for notes which are or were suspended
+    let mut suspensions = Vec::new();
+    // anki id and policy for notes whose revision changed
+    let mut revised = Vec::new();
+    // notes which aren't updated but whose tags for the suspension or the revision changed
+    let mut retagged: Vec<NoteWithInfo> = Vec::new();
+    let update = args.update || config.update;
+    let mut skipped_notes = 0;
 
     debug!("checking notes");
-    for inner_meta in metadata.0 {
-        let mut note = inner_meta.value;
+    for mut note in notes {
         let Some(model) = state.models.get(&note.model) else {
             error!("create note with invalid model name {}", note.model);
-            return Ok(());
+            return Ok(false);
         };
         for field_name in note.fields.keys() {
             if !model.field_names.contains(field_name) {
@@ -171,46 +352,190 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
                     note.model, field_name
                 );
                 info!("field names: {}", model.field_names.join(", "));
-                return Ok(());
+                info!(
+                    "map the field with `field_map` or set `add_missing_fields` for the model in the config"
+                );
+                return Ok(false);
             }
         }
 
-        if config.add_generated {
-            note.tags.push(String::from("generated"));
+        let (id_field, id_field_configured) = config.id_field(&note.model);
+        let has_id_field = model.field_names.iter().any(|name| name == id_field);
+        if id_field_configured && !has_id_field {
+            error!(
+                "model {} does not contain the id field `{}`",
+                note.model, id_field
+            );
+            info!("field names: {}", model.field_names.join(", "));
+            return Ok(false);
+        }
+        let source_field = config.source_field(&note.model);
+        if let Some(source_field) = source_field {
+            if !model.field_names.iter().any(|name| name == source_field) {
+                error!(
+                    "model {} does not contain the source field `{}`",
+                    note.model, source_field
+                );
+                info!("field names: {}", model.field_names.join(", "));
+                return Ok(false);
+            }
+        }
+        let media = attachments(&note, files.media_base)?;
+        if let Some((_, data)) = media.iter().find(|(_, data)| {
+            data.fields
+                .iter()
+                .any(|field| !model.field_names.contains(field))
+        }) {
+            error!(
+                "model {} does not contain the fields {} of media {}",
+                note.model,
+                data.fields.join(", "),
+                data.filename
+            );
+            info!("field names: {}", model.field_names.join(", "));
+            return Ok(false);
+        }
+        let source = files
+            .sources
+            .zip(note.id.as_deref())
+            .and_then(|(sources, id)| sources.find(id));
+        if files.sources.is_some() && source.is_none() {
+            debug!(
+                "could not find the source of note {}",
+                note.id.as_deref().unwrap_or_default()
+            );
+        }
+        let hash = note_hash(&note, config, &media, files.output);
+
+        let was_suspended =
+            |existing: &NoteWithInfo| existing.note.tags.iter().any(|tag| tag == tags::SUSPENDED);
+        let existing = state
+            .find_note_by_id(&note)
+            .and_then(|existing| Some((existing.id?, existing)));
+        // the suspension and the revision aren't part of the hash, so they are handled even if
+        // the note isn't updated
+        if let Some((anki_id, existing)) = existing {
+            if note.suspended() || was_suspended(existing) {
+                suspensions.push((anki_id, note.suspended()));
+            }
+            let old_revision = tags::find_revision(&existing.note.tags);
+            // setting the first revision (or removing it) doesn't change the meaning of the note
+            if old_revision.is_some() && note.revision.is_some() && note.revision != old_revision {
+                let policy = args
+                    .on_revision
+                    .or(note.on_revision)
+                    .unwrap_or(config.on_revision);
+                debug!(
+                    "revision of note {} changed from {:?} to {:?}",
+                    anki_id, old_revision, note.revision
+                );
+                revised.push((anki_id, policy));
+            }
+        }
+        match existing {
+            Some((_, existing))
+                if !update || tags::find_hash(&existing.note.tags) == Some(hash) =>
+            {
+                if tags::find_hash(&existing.note.tags) != Some(hash) {
+                    warn!(
+                        "note with id {} in deck {} changed, not updating it",
+                        note.id.as_deref().unwrap_or_default(),
+                        note.deck
+                    );
+                    skipped_notes += 1;
+                }
+                let tags = tags::with_state(
+                    &existing.note.tags,
+                    note.suspended(),
+                    note.revision.as_deref(),
+                );
+                if tags != existing.note.tags {
+                    let mut existing = existing.clone();
+                    existing.note.tags = tags;
+                    retagged.push(existing);
+                }
+                continue;
+            }
+            Some(_) => {
+                debug!(
+                    "note with id {} in deck {} exists but its content changed",
+                    note.id.as_deref().unwrap_or_default(),
+                    note.deck
+                );
+            }
+            None => {
+                if let Some(found) = state.find_note(&note) {
+                    if let (Some(anki_id), true) =
+                        (found.id, note.suspended() || was_suspended(found))
+                    {
+                        suspensions.push((anki_id, note.suspended()));
+                    }
+                    continue;
+                }
+            }
         }
 
+        if config.add_generated {
+            note.tags.push(String::from(tags::GENERATED));
+        }
         if let Some(date) = &config.add_generation_date {
-            note.tags.push(date.clone());
+            note.tags.push(tags::date(date));
         }
-
-        // if state.added_notes.contains(&note)
-        if state.added_notes.iter().any(|y| *y == note) {
-            continue;
+        if let Some(id) = &note.id {
+            if config.id_tag {
+                note.tags.push(tags::id(id));
+            }
+        }
+        if let (Some(source), true) = (&source, config.source_tag) {
+            note.tags.push(tags::source(source));
         }
+        if note.suspended() {
+            note.tags.push(String::from(tags::SUSPENDED));
+        }
+        if let Some(revision) = &note.revision {
+            note.tags.push(tags::revision(revision));
+        }
+        note.tags.push(tags::hash(hash));
 
         let mut fields = HashMap::with_capacity(note.fields.len());
+        let text_layer = config.text_layer(&note.model);
+        let mut text_fields = Vec::new();
         for (name, value) in &note.fields {
             let content = match value {
                 Field::Raw(val) => val.clone(),
                 Field::Plain { plain } => plain.clone(),
+                Field::Math { mathjax, .. } => template::escape_html(mathjax),
                 Field::Content {
                     content,
                     page_start,
                     page_end,
+                    ..
                 } => {
                     let mut field = String::new();
                     assert!(page_start <= page_end);
-                    let mut is_first = true;
-                    for page_number in *page_start..=*page_end {
+                    let template = config.image_template(&note.model, name);
+                    for (page, page_number) in (*page_start..=*page_end).enumerate() {
                         let res = build_note_field_with_img(
-                            &output,
+                            backend,
+                            files.output,
                             &note,
-                            content,
-                            is_first,
-                            page_number,
+                            ImageInfo {
+                                field: name,
+                                content,
+                                page: page + 1,
+                                page_number,
+                            },
+                            template,
                         )?;
                         field.push_str(&res);
-                        is_first = false;
+                    }
+                    let text = template::escape_html(value.searchable_text().unwrap_or_default());
+                    match text_layer {
+                        TextLayer::None => {}
+                        TextLayer::Span => field.push_str(&format!(
+                            "<span class=\"anki-typst-text\" style=\"display: none\">{text}</span>"
+                        )),
+                        TextLayer::Field => text_fields.push((format!("{name}_text"), text)),
                     }
 
                     field
@@ -219,17 +544,57 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
             };
             fields.insert(name.clone(), content);
         }
+        for (name, text) in text_fields {
+            if model.field_names.contains(&name) {
+                fields.insert(name, text);
+            } else {
+                debug!("model {} has no field {} for the text", note.model, name);
+            }
+        }
+        if let (Some(id), true) = (&note.id, has_id_field) {
+            fields
+                .entry(String::from(id_field))
+                .or_insert_with(|| id.clone());
+        }
+        if let (Some(source), Some(source_field)) = (&source, source_field) {
+            fields.insert(String::from(source_field), source.to_string());
+        }
+
+        let (mut audio, mut video, mut picture) = (Vec::new(), Vec::new(), Vec::new());
+        for (kind, data) in media {
+            match kind {
+                MediaKind::Audio => audio.push(data),
+                MediaKind::Video => video.push(data),
+                MediaKind::Picture => picture.push(data),
+            }
+        }
 
+        let mut tags = note.tags.clone();
+        if let Some((_, existing)) = existing {
+            // tags added in anki are kept
+            let added_in_anki = existing
+                .note
+                .tags
+                .iter()
+                .filter(|tag| !tags::is_generated(tag) && !note.tags.contains(tag))
+                .cloned()
+                .collect::<Vec<_>>();
+            tags.extend(added_in_anki);
+        }
         let api_note = api::Note {
             deck_name: note.deck.clone(),
             model_name: note.model.clone(),
             fields,
-            tags: note.tags.clone(),
-            picture: SingleOrMulti::default(),
-            audio: SingleOrMulti::default(),
-            video: SingleOrMulti::default(),
+            tags,
+            picture: SingleOrMulti::Multi(picture),
+            audio: SingleOrMulti::Multi(audio),
+            video: SingleOrMulti::Multi(video),
         };
 
+        if let Some((anki_id, _)) = existing {
+            updated_notes.push((anki_id, note, api_note));
+            continue;
+        }
         let (notes, api_notes) = note_decks.entry(note.deck.clone()).or_default();
         notes.push(note);
         api_notes.push(api_note);
@@ -237,6 +602,7 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
     debug!("checked notes");
 
     let mut global_added_notes = 0;
+    let mut changes = Vec::new();
 
     for (deck, (notes, api_notes)) in note_decks {
         // TODO id
@@ -246,13 +612,13 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
                 "create all decks in the file with `{} create-all-decks`",
                 BIN_NAME
             );
-            return Ok(());
+            return Ok(false);
         }
         info!("creating {} notes in deck {}", notes.len(), deck);
 
         let mut duplicates = 0;
         let mut added_notes = 0;
-        let ids = add_notes(&api_notes)?;
+        let ids = backend.add_notes(&api_notes)?;
         for (id, note) in ids.into_iter().zip(notes) {
             if id.is_none() {
                 duplicates += 1;
@@ -267,12 +633,25 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
                     &note.deck, note.fields,
                 );
             }
+            if let (Some(id), true) = (id, note.suspended()) {
+                suspensions.push((id, true));
+            }
+            if let Some(id) = id {
+                changes.push(Change {
+                    action: Action::Created,
+                    note_id: id,
+                    typst_id: note.id.clone(),
+                    deck: note.deck.clone(),
+                    model: note.model.clone(),
+                    before: None,
+                });
+            }
             let note = NoteWithInfo {
                 id,
                 note,
                 question: None,
             };
-            state.added_notes.push(note);
+            state.add_note(note);
         }
 
         if duplicates != 0 {
@@ -287,22 +666,367 @@ fn update_change(state: &mut State, config: &Config, path: &Path, args: &CreateA
         }
     }
 
-    if global_added_notes == 0 {
+    let mut before = match &journal {
+        Some(journal) => {
+            let ids = updated_notes
+                .iter()
+                .map(|(anki_id, _, _)| *anki_id)
+                .collect::<Vec<_>>();
+            let last_written = match state.last_written.take() {
+                Some(last_written) => last_written,
+                None => journal.last_written()?,
+            };
+            // a dry run records nothing, so the media doesn't have to be stored
+            let before = if args.dry_run {
+                journal::current(backend, &ids)?
+            } else {
+                journal.snapshots(backend, &ids, &last_written)?
+            };
+            let policy = args.on_anki_edit.unwrap_or(config.on_anki_edit);
+            anki_edits::resolve(
+                policy,
+                &last_written,
+                &before,
+                &mut updated_notes,
+                args.dry_run,
+            )?;
+            // tags which were removed in typst aren't kept as tags added in anki
+            for (anki_id, note, api_note) in &mut updated_notes {
+                if let Some(last) = last_written.get(anki_id) {
+                    api_note
+                        .tags
+                        .retain(|tag| note.tags.contains(tag) || !last.tags.contains(tag));
+                }
+            }
+            state.last_written = Some(last_written);
+            before
+        }
+        None => HashMap::new(),
+    };
+    for (anki_id, note, api_note) in &updated_notes {
+        changes.push(Change {
+            action: Action::Updated,
+            note_id: *anki_id,
+            typst_id: note.id.clone(),
+            deck: note.deck.clone(),
+            model: note.model.clone(),
+            before: before.remove(anki_id),
+        });
+        debug!(
+            "updating note {} in deck {} with fields {:?}",
+            anki_id, note.deck, note.fields
+        );
+        backend.update_note(*anki_id, api_note)?;
+    }
+    let global_updated_notes = updated_notes.len();
+    for (anki_id, mut note, api_note) in updated_notes {
+        note.tags = api_note.tags;
+        state.add_note(NoteWithInfo {
+            id: Some(anki_id),
+            note,
+            question: None,
+        });
+    }
+
+    for note in retagged {
+        let Some(anki_id) = note.id else { continue };
+        debug!("updating the tags of note {}", anki_id);
+        // without fields, only the tags are changed
+        backend.update_note(
+            anki_id,
+            &api::Note {
+                deck_name: note.note.deck.clone(),
+                model_name: note.note.model.clone(),
+                tags: note.note.tags.clone(),
+                ..Default::default()
+            },
+        )?;
+        state.add_note(note);
+    }
+    update_suspended(backend, &suspensions)?;
+    reschedule_revised(backend, &revised)?;
+    if let Some(journal) = journal.as_ref().filter(|_| !args.dry_run) {
+        let written = journal.record(backend, changes)?;
+        // the last entry of each note is never pruned
+        journal.prune(config.journal_runs)?;
+        if let Some(last_written) = &mut state.last_written {
+            last_written.extend(written);
+        }
+    }
+
+    if global_added_notes == 0 && global_updated_notes == 0 && skipped_notes == 0 {
         info!("nothing to do :)");
-    } else {
+    }
+    if global_added_notes != 0 {
         info!("added {} new notes", global_added_notes);
     }
+    if global_updated_notes != 0 {
+        info!("updated {} notes", global_updated_notes);
+    }
+    if skipped_notes != 0 {
+        warn!("{} changed notes were not updated", skipped_notes);
+        info!("update them with `create --update` or `update = true` in the config");
+    }
+
+    Ok(true)
+}
+
+/// Add the fields of `notes` which their model doesn't have, for models with `add_missing_fields`.
+fn add_missing_fields(
+    state: &mut State,
+    backend: &dyn Backend,
+    config: &Config,
+    notes: &[Note],
+) -> Result<()> {
+    for note in notes {
+        if !config.add_missing_fields(&note.model) {
+            continue;
+        }
+        let Some(model) = state.models.get_mut(&note.model) else {
+            continue;
+        };
+        let fields = note
+            .fields
+            .keys()
+            .chain(note.media.iter().flat_map(|media| &media.fields));
+        for field in fields {
+            if model.field_names.contains(field) {
+                continue;
+            }
+            backend
+                .model_field_add(&note.model, field, model.field_names.len())
+                .with_note(|| eyre!("while adding field {} to model {}", field, note.model))?;
+            info!("added field {} to model {}", field, note.model);
+            model.field_names.push(field.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Query the notes of `path`, changed by the `rewrite` rules of the config
+/// and with the deck prefix and tags of the selected target.
+///
+/// Afterwards the notes are transformed by the `hook` of the config, if any,
+/// and their fields are mapped to the fields of their model.
+fn query_notes(path: &Path, config: &Config) -> Result<Vec<Note>> {
+    let metadata = query(&path.to_string_lossy(), &config.typst)?;
+    let notes = metadata
+        .0
+        .into_iter()
+        .map(|inner| {
+            let mut note = inner.value;
+            rewrite::apply(&config.rewrite, &mut note);
+            note.deck = config.target_deck(&note.deck);
+            note.tags.extend(config.target_tags().iter().cloned());
+            note
+        })
+        .collect::<Vec<_>>();
+    let mut notes = if config.hook.is_empty() {
+        notes
+    } else {
+        let target = config.target.as_ref().map(|target| target.name.as_str());
+        hook::run(&config.hook, &notes, path, target)?
+    };
+    for note in &mut notes {
+        rewrite::map_fields(config, note);
+    }
+    Ok(notes)
+}
+
+/// Suspend the cards of notes which are drafts or retired in typst
+/// and unsuspend them once the flag is removed.
+///
+/// `notes` are the anki ids and whether the cards should be suspended.
+/// Only notes which were suspended by anki-typst (see [`tags::SUSPENDED`]) should be unsuspended,
+/// so cards suspended in anki stay suspended.
+fn update_suspended(backend: &dyn Backend, notes: &[(usize, bool)]) -> Result<()> {
+    if notes.is_empty() {
+        return Ok(());
+    }
+    let suspend = notes.iter().copied().collect::<HashMap<_, _>>();
+    let ids = suspend.keys().copied().collect::<Vec<_>>();
+    let mut cards = Vec::new();
+    for ids in ids.chunks(400) {
+        for info in backend.notes_info(ids)? {
+            cards.extend(
+                info.cards
+                    .into_iter()
+                    .map(|card| (card, suspend[&info.note_id])),
+            );
+        }
+    }
+    let card_ids = cards.iter().map(|(card, _)| *card).collect::<Vec<_>>();
+    let (mut to_suspend, mut to_unsuspend) = (Vec::new(), Vec::new());
+    for ((card, suspend), suspended) in cards.into_iter().zip(backend.are_suspended(&card_ids)?) {
+        match (suspend, suspended) {
+            (true, Some(false)) => to_suspend.push(card),
+            (false, Some(true)) => to_unsuspend.push(card),
+            _ => {}
+        }
+    }
+
+    if !to_suspend.is_empty() {
+        backend.suspend(&to_suspend)?;
+        info!(
+            "suspended {} cards of drafts or retired notes",
+            to_suspend.len()
+        );
+    }
+    if !to_unsuspend.is_empty() {
+        backend.unsuspend(&to_unsuspend)?;
+        info!("unsuspended {} cards", to_unsuspend.len());
+    }
 
     Ok(())
 }
 
+/// Apply the [`RevisionPolicy`] to the cards of notes whose revision changed.
+fn reschedule_revised(backend: &dyn Backend, notes: &[(usize, RevisionPolicy)]) -> Result<()> {
+    for policy in [RevisionPolicy::Reset, RevisionPolicy::Relearn] {
+        let ids = notes
+            .iter()
+            .filter(|(_, p)| *p == policy)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            continue;
+        }
+        let mut cards = Vec::new();
+        for ids in ids.chunks(400) {
+            cards.extend(
+                backend
+                    .notes_info(ids)?
+                    .into_iter()
+                    .flat_map(|info| info.cards),
+            );
+        }
+        match policy {
+            RevisionPolicy::Reset => {
+                backend.forget_cards(&cards)?;
+                info!("reset {} cards of revised notes", cards.len());
+            }
+            RevisionPolicy::Relearn => {
+                backend.relearn_cards(&cards)?;
+                info!("relearning {} cards of revised notes", cards.len());
+            }
+            RevisionPolicy::Keep => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the files attached to `note`, their paths are relative to `base`.
+fn attachments(note: &Note, base: &Path) -> Result<Vec<(MediaKind, MediaData)>> {
+    note.media
+        .iter()
+        .map(|media| {
+            let kind = media.kind().ok_or_else(|| {
+                eyre!("can't guess the kind of media file {}", media.path)
+                    .with_note(|| "set `kind` to `audio`, `video` or `picture`")
+            })?;
+            let path = base.join(media.path.trim_start_matches('/'));
+            let data = std::fs::read(&path).with_note(|| {
+                eyre!(
+                    "while reading media file {} of note {}",
+                    path.display(),
+                    note.id.as_deref().unwrap_or_default()
+                )
+            })?;
+            let name = path
+                .file_name()
+                .ok_or_else(|| eyre!("media file has no name: {}", path.display()))?
+                .to_string_lossy();
+            let filename = format!("{:016x}_{name}", fasthash::metro::hash64(&data));
+            Ok((
+                kind,
+                MediaData {
+                    filename,
+                    inner: MediaDataInner::Data(
+                        base64::engine::general_purpose::STANDARD.encode(data),
+                    ),
+                    delete_existing: false,
+                    fields: media.fields.clone(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Hash of everything anki-typst writes for `note`, including the rendered pages.
+///
+/// It is stored in a tag, so changed notes can be found even when their fields only contain images.
+/// Unlike [`Note::fingerprint`] it must be stable between runs and rust versions.
+/// The source isn't part of it, it is only guessed from the typst id (see [`SourceIndex::find`]).
+/// The suspension and the revision aren't part of it, their tags are updated separately.
+fn note_hash(
+    note: &Note,
+    config: &Config,
+    media: &[(MediaKind, MediaData)],
+    output: &ThemedCompileOutput,
+) -> u64 {
+    let outputs = match output {
+        ThemedCompileOutput::Light(out) | ThemedCompileOutput::Dark(out) => vec![out],
+        ThemedCompileOutput::Both { light, dark } => vec![light, dark],
+    };
+
+    let mut hasher = fasthash::metro::Hasher64_1::default();
+    note.hash_fingerprint(&mut hasher);
+    note.id.hash(&mut hasher);
+    for (kind, data) in media {
+        // the filename contains the hash of the content
+        kind.hash(&mut hasher);
+        data.filename.hash(&mut hasher);
+        data.fields.hash(&mut hasher);
+    }
+    for (name, value) in &note.fields {
+        if let Field::Content {
+            page_start,
+            page_end,
+            ..
+        } = value
+        {
+            name.hash(&mut hasher);
+            // only hashed if configured, so the hash of existing notes doesn't change
+            if let Some(template) = config.image_template(&note.model, name) {
+                template.hash(&mut hasher);
+            }
+            let text_layer = config.text_layer(&note.model);
+            if text_layer != TextLayer::None {
+                text_layer.hash(&mut hasher);
+                value.searchable_text().hash(&mut hasher);
+            }
+            for out in &outputs {
+                for page_number in *page_start..=*page_end {
+                    out.files
+                        .get(&page_number)
+                        .map(|(_path, data)| data)
+                        .hash(&mut hasher);
+                }
+            }
+        }
+    }
+    hasher.finish()
+}
+
+/// A single page of a field rendered by typst.
+struct ImageInfo<'a> {
+    field: &'a str,
+    content: &'a str,
+    /// 1-based index of the page in the field
+    page: usize,
+    /// Page number in the compiled document
+    page_number: usize,
+}
+
 fn build_note_field_with_img(
+    backend: &dyn Backend,
     output: &ThemedCompileOutput,
     note: &Note,
-    content: &String,
-    is_first: bool,
-    page_number: usize,
+    image: ImageInfo,
+    template: Option<&str>,
 ) -> Result<String> {
+    let page_number = image.page_number;
     let get_data = |files: &CompileOutput| {
         let Some((_path, encoded_data)) = files.files.get(&page_number).cloned() else {
             bail!(
@@ -311,19 +1035,51 @@ fn build_note_field_with_img(
                 note.fields
             );
         };
-        let filename = api::store_media_file(&MediaData {
+        let filename = backend.store_media_file(&MediaData {
             filename: match &note.id {
                 Some(val) => format!("{val}_page{page_number}.svg"),
                 None => bail!("note requires id: {:?}", note),
             },
             inner: MediaDataInner::Data(encoded_data),
             delete_existing: false,
+            fields: Vec::new(),
         })?;
         Ok(filename)
     };
 
-    let alt = if is_first {
-        format!(" alt=\"{content}\"")
+    if let Some(template) = template {
+        let render = |filename: &str, theme: &str, class: &str| {
+            let page = image.page.to_string();
+            let page_number = page_number.to_string();
+            template::render(
+                template,
+                &[
+                    ("filename", filename),
+                    ("page", &page),
+                    ("page_number", &page_number),
+                    ("theme", theme),
+                    ("class", class),
+                    ("alt", image.content),
+                    ("id", note.id.as_deref().unwrap_or_default()),
+                    ("deck", &note.deck),
+                    ("model", &note.model),
+                    ("field", image.field),
+                ],
+            )
+        };
+        return match output {
+            ThemedCompileOutput::Light(out) => render(&get_data(out)?, "light", ""),
+            ThemedCompileOutput::Dark(out) => render(&get_data(out)?, "dark", ""),
+            ThemedCompileOutput::Both { light, dark } => Ok(format!(
+                "{}{}",
+                render(&get_data(light)?, "light", "lighttheme")?,
+                render(&get_data(dark)?, "dark", "darktheme")?
+            )),
+        };
+    }
+
+    let alt = if image.page == 1 {
+        format!(" alt=\"{}\"", template::escape_html(image.content))
     } else {
         String::new()
     };
@@ -370,9 +1126,18 @@ struct Args {
     /// Add a tag with the value `generated` for each new note.
     #[arg(long, default_value = "true")]
     add_generated: bool,
-    /// Add a tag with the value `generated@$date` for each new note.
+    /// Add the tag `anki-typst::date::$date` for each new or updated note.
     #[arg(long, default_value = "true")]
     add_generation_date: bool,
+    /// Address of AnkiConnect, overrides the value from the config file.
+    #[arg(long)]
+    anki_endpoint: Option<String>,
+    /// Anki profile to write to, overrides the value from the config file.
+    #[arg(long)]
+    profile: Option<String>,
+    /// Write to the target with this name from the config file.
+    #[arg(long, global = true)]
+    target: Option<String>,
 
     #[command(subcommand)]
     subcommand: Commands,
@@ -387,10 +1152,12 @@ enum Commands {
     #[clap(visible_alias = "c")]
     Create(CreateArgs),
     /// Create all decks in the file if they don't exist already
-    CreateAllDecks,
+    CreateAllDecks(TypstArgs),
+    /// Apply the deck presets of the config to all existing decks
+    ApplyDeckOptions,
     /// Create the default `anki-typst` model
     CreateDefaultModel {
-        #[arg(default_value = "anki-typst")]
+        #[arg(default_value = DEFAULT_MODEL)]
         model_name: String,
     },
     /// Get all deck names
@@ -406,16 +1173,154 @@ enum Commands {
     /// Sync all notes to ankiweb
     #[clap(visible_alias = "s")]
     Sync,
+    /// Show review statistics of the notes in the file, summarized per section
+    Stats(StatsArgs),
+    /// Render the cards to html with the card templates and css of their models
+    Preview(PreviewArgs),
+    /// Print the location in the typst sources of a note
+    Locate {
+        /// Typst id or anki note id
+        note: String,
+        /// Open the location in `$VISUAL` or `$EDITOR`
+        #[arg(long)]
+        edit: bool,
+    },
+    /// Show the changes of a note recorded in the journal, or all runs without a note
+    History {
+        /// Typst id or anki note id
+        note: Option<String>,
+    },
+    /// Restore a note or all notes changed by a run from the journal
+    #[command(arg_required_else_help = true)]
+    Rollback {
+        /// Typst id or anki note id, the last change of the note is undone
+        #[arg(required_unless_present = "run", conflicts_with = "run")]
+        note: Option<String>,
+        /// Restore the note as it was written by this revision, see `history`
+        #[arg(long, requires = "note")]
+        revision: Option<usize>,
+        /// Undo all changes of this run, see `history` without a note
+        #[arg(long)]
+        run: Option<String>,
+    },
+    /// Serve a local AnkiConnect compatible stub for testing without anki
+    ServeStub {
+        #[arg(long, default_value = "8766")]
+        port: u16,
+        /// Load and store the collection in this json file
+        #[arg(long)]
+        state: Option<PathBuf>,
+        /// Profiles which can be loaded, the first one is active. Can be given multiple times
+        #[arg(long = "profile")]
+        profiles: Vec<String>,
+    },
 }
 
 #[derive(Debug, clap::Args)]
 struct CreateArgs {
-    /// Set the theme for images
-    #[arg(long, value_enum, default_value_t = Theme::Both)]
-    theme: Theme,
+    /// Set the theme for images [default: theme of the target or both]
+    #[arg(long, value_enum)]
+    theme: Option<Theme>,
+    /// Wait until anki is available instead of failing if it can't be reached
+    #[arg(long)]
+    wait_for_anki: bool,
+    /// Don't change anything in anki, only show what would be done
+    #[arg(long)]
+    dry_run: bool,
+    /// Fetch all notes from anki to find existing notes, not only the ones in the same decks
+    #[arg(long)]
+    full_scan: bool,
+    /// Update notes whose content changed in typst, even if `update` isn't set in the config
+    #[arg(long)]
+    update: bool,
+    /// What happens to the cards of notes whose `revision` changed, overrides `on_revision` in typst
+    #[arg(long, value_enum)]
+    on_revision: Option<RevisionPolicy>,
+    /// What happens to fields which were edited in anki since they were written last, overrides `on_anki_edit` in the config
+    #[arg(long, value_enum)]
+    on_anki_edit: Option<AnkiEditPolicy>,
+    /// Write to all targets from the config file, each with its own sync state
+    #[arg(long)]
+    all_targets: bool,
+    #[command(flatten)]
+    typst: TypstArgs,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[derive(Debug, clap::Args)]
+struct PreviewArgs {
+    /// Directory to write the html and media files to
+    #[arg(short, long, default_value = "anki-preview")]
+    out: PathBuf,
+    /// Render again when the file changes, the opened page reloads itself
+    #[arg(long)]
+    watch: bool,
+    /// Set the theme for images [default: theme of the target or both]
+    #[arg(long, value_enum)]
+    theme: Option<Theme>,
+    #[command(flatten)]
+    typst: TypstArgs,
+}
+
+#[derive(Debug, clap::Args)]
+struct StatsArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t = stats::Format::Text)]
+    format: stats::Format,
+    /// Number of headings which make up a section
+    #[arg(long, default_value = "1")]
+    depth: usize,
+    /// Number of hardest notes to show
+    #[arg(long, default_value = "10")]
+    top: usize,
+    #[command(flatten)]
+    typst: TypstArgs,
+}
+
+/// Arguments passed on to typst.
+///
+/// These extend the `typst` section of the config file.
+#[derive(Debug, Clone, clap::Args)]
+struct TypstArgs {
+    /// Project root for typst
+    #[arg(long)]
+    root: Option<PathBuf>,
+    /// Additional directory to search for fonts. Can be given multiple times
+    #[arg(long = "font-path")]
+    font_paths: Vec<PathBuf>,
+    /// Custom path to local typst packages
+    #[arg(long)]
+    package_path: Option<PathBuf>,
+    /// Additional input for `sys.inputs` in the form `key=value`. Can be given multiple times
+    #[arg(long = "input", value_parser = parse_key_value)]
+    inputs: Vec<(String, String)>,
+    /// How math in fields which aren't rendered as images is written to anki
+    #[arg(long, value_enum)]
+    plain_math: Option<PlainMath>,
+}
+
+impl From<TypstArgs> for TypstOptions {
+    fn from(args: TypstArgs) -> Self {
+        Self {
+            root: args.root,
+            font_paths: args.font_paths,
+            package_path: args.package_path,
+            inputs: args.inputs.into_iter().collect(),
+            plain_math: args.plain_math,
+        }
+    }
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String)> {
+    let Some((key, value)) = s.split_once('=') else {
+        bail!("expected `key=value` but got `{}`", s);
+    };
+    Ok((String::from(key), String::from(value)))
+}
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 enum Theme {
     // Create cards with dark theme
     Dark,
@@ -439,7 +1344,7 @@ fn main() -> Result<()> {
         tracing::subscriber::set_global_default(builder.finish())?;
     }
 
-    let config = Config::load(
+    let mut config = Config::load(
         args.add_generated,
         args.add_generation_date
             .then(|| format!("{}", chrono::Local::now().format("%Y-%m-%d"))),
@@ -450,24 +1355,50 @@ fn main() -> Result<()> {
         .or_else(|| config.path.clone())
         .unwrap_or_else(|| "anki.typ".into());
     // drop args so it can't be used later on
-    let Args { subcommand, .. } = args;
+    let Args {
+        subcommand,
+        anki_endpoint,
+        profile,
+        target,
+        ..
+    } = args;
+    // the command line takes precedence over the target and the config file
+    let override_config = |config: &mut Config| {
+        if let Some(endpoint) = &anki_endpoint {
+            config.anki.endpoint = endpoint.clone();
+        }
+        if let Some(profile) = &profile {
+            config.profile = Some(profile.clone());
+        }
+    };
+    if let Some(target) = &target {
+        config = config.with_target(target)?;
+    }
+    override_config(&mut config);
+
+    let client = AnkiConnect::new(config.anki.clone());
 
     match subcommand {
-        Commands::Watch(args) => watch(&config, &main_path, &args)?,
+        Commands::Watch(args) => {
+            config.typst.merge(args.typst.clone().into());
+            let configs = target_configs(&config, target.as_deref(), &args, override_config)?;
+            watch(&configs, &main_path, &args)?;
+        }
         Commands::Create(args) => {
-            let mut state = State::new()?;
-            update_change(&mut state, &config, &main_path, &args)?;
+            config.typst.merge(args.typst.clone().into());
+            let configs = target_configs(&config, target.as_deref(), &args, override_config)?;
+            create(&configs, &main_path, &args)?;
         }
         Commands::GetDecks => {
-            let names = get_deck_names()?;
-            println!("All deck names: \n {}", names.0.join("\n "));
+            let names = client.deck_names()?;
+            println!("All deck names: \n {}", names.join("\n "));
         }
         Commands::GetModels => {
-            let names = get_model_names()?;
-            println!("All model names: \n {}", names.0.join("\n "));
+            let names = client.model_names()?;
+            println!("All model names: \n {}", names.join("\n "));
         }
         Commands::GetNotes { query } => {
-            let notes = get_notes(&query)?;
+            let notes = get_notes(&client, &config, &query, None)?;
             let notes_len = notes.len();
 
             for note in notes {
@@ -486,25 +1417,273 @@ fn main() -> Result<()> {
 
             println!("fetched {notes_len} notes in total");
         }
-        Commands::CreateAllDecks => {
-            create_all_decks(&main_path)?;
+        Commands::CreateAllDecks(args) => {
+            config.typst.merge(args.into());
+            ensure_profile(&client, &config)?;
+            create_all_decks(&client, &main_path, &config)?;
+        }
+        Commands::ApplyDeckOptions => {
+            if config.deck_options.is_empty() {
+                warn!("no deck options are configured");
+            }
+            ensure_profile(&client, &config)?;
+            let decks = client.deck_names()?;
+            deck_options::apply(&client, &config, &decks)?;
         }
         Commands::CreateDefaultModel { model_name } => {
-            create_default_model(&model_name)?;
+            ensure_profile(&client, &config)?;
+            create_default_model(&client, &model_name)?;
         }
         Commands::Sync => {
+            ensure_profile(&client, &config)?;
             info!("syncing all notes");
-            sync()?;
+            client.sync()?;
             println!("Success");
         }
+        Commands::Stats(args) => {
+            config.typst.merge(args.typst.clone().into());
+            print_stats(&client, &config, &main_path, &args)?;
+        }
+        Commands::Preview(args) => {
+            config.typst.merge(args.typst.clone().into());
+            preview(&client, &config, &main_path, &args)?;
+        }
+        Commands::Locate { note, edit } => {
+            locate(&client, &config, &main_path, &note, edit)?;
+        }
+        Commands::History { note } => {
+            let journal = Journal::new(journal_dir(&config)?);
+            match note {
+                Some(note) => journal::print_history(&journal.history(&note)?),
+                None => journal::print_runs(&journal.load()?),
+            }
+        }
+        Commands::Rollback {
+            note,
+            revision,
+            run,
+        } => {
+            let journal = Journal::new(journal_dir(&config)?);
+            ensure_profile(&client, &config)?;
+            match (note, run) {
+                (_, Some(run)) => journal.rollback_run(&client, &run)?,
+                (Some(note), None) => journal.rollback_note(&client, &note, revision)?,
+                (None, None) => unreachable!("clap requires a note or a run"),
+            }
+        }
+        Commands::ServeStub {
+            port,
+            state,
+            profiles,
+        } => {
+            stub::serve(port, state.as_deref(), profiles)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn journal_dir(config: &Config) -> Result<&Path> {
+    config.journal_dir.as_deref().ok_or_else(|| {
+        eyre!("the journal is disabled").with_note(|| "set `journal = true` in the config")
+    })
+}
+
+/// Run `f` and rerun it after Anki is available again if it couldn't be reached.
+///
+/// This only waits if `--wait-for-anki` is given.
+fn with_wait_for_anki<T>(
+    client: &AnkiConnect,
+    args: &CreateArgs,
+    mut f: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    if args.wait_for_anki {
+        client.wait_until_available()?;
+    }
+    loop {
+        match f() {
+            Err(e) if args.wait_for_anki && api::is_unreachable(&e) => {
+                warn!("lost connection to anki: {}", e);
+                client.wait_until_available()?;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Get the backend to use for `create` and `watch`.
+///
+/// This is anki itself or an [`Overlay`] of it for dry runs.
+fn create_backend(client: &AnkiConnect, args: &CreateArgs) -> Result<Box<dyn Backend>> {
+    if args.dry_run {
+        info!("dry run: no changes will be written to anki");
+        Ok(Box::new(Overlay::new(client.clone())?))
+    } else {
+        Ok(Box::new(client.clone()))
+    }
+}
+
+/// Make sure anki has the profile of the config open, before anything is written to it.
+///
+/// Anki is switched to the profile if another one is open.
+/// Fails if the profile can't be opened, so nothing is written into the wrong collection.
+fn ensure_profile(backend: &dyn Backend, config: &Config) -> Result<()> {
+    let Some(profile) = &config.profile else {
+        return Ok(());
+    };
+    let active = backend.active_profile()?;
+    if active == *profile {
+        debug!("profile {} is open", profile);
+        return Ok(());
+    }
+    info!("switching anki from profile {} to {}", active, profile);
+    backend
+        .load_profile(profile)
+        .with_note(|| eyre!("refusing to write into profile {}", active))?;
+    let active = backend.active_profile()?;
+    if active != *profile {
+        bail!(
+            "refusing to write into profile {}, expected profile {}",
+            active,
+            profile
+        );
+    }
+    Ok(())
+}
+
+/// Make sure anki has the profile of the config open, without switching to it.
+///
+/// Used for dry runs, which must not change anything in anki.
+fn check_profile(backend: &dyn Backend, config: &Config) -> Result<()> {
+    let Some(profile) = &config.profile else {
+        return Ok(());
+    };
+    let active = backend.active_profile()?;
+    if active != *profile {
+        return Err(eyre!(
+            "profile {} is open, expected profile {}",
+            active,
+            profile
+        ))
+        .suggestion(format!(
+            "open the profile {} in anki, a dry run doesn't switch profiles",
+            profile
+        ));
+    }
+    debug!("profile {} is open", profile);
+    Ok(())
+}
+
+/// Make sure the right profile is open before a run, see [`ensure_profile`] and [`check_profile`].
+fn prepare_profile(backend: &dyn Backend, config: &Config, args: &CreateArgs) -> Result<()> {
+    if args.dry_run {
+        check_profile(backend, config)
+    } else {
+        ensure_profile(backend, config)
+    }
+}
+
+/// Configs for all targets with `--all-targets`, otherwise only `config`.
+fn target_configs(
+    config: &Config,
+    target: Option<&str>,
+    args: &CreateArgs,
+    override_config: impl Fn(&mut Config),
+) -> Result<Vec<Config>> {
+    if !args.all_targets {
+        return Ok(vec![config.clone()]);
+    }
+    if let Some(target) = target {
+        bail!(
+            "`--all-targets` can't be combined with `--target {}`",
+            target
+        );
+    }
+    if config.targets.is_empty() {
+        return Err(eyre!("no targets are configured"))
+            .suggestion("add targets in the `targets` section of the config file");
+    }
+    config
+        .targets
+        .keys()
+        .map(|name| {
+            let mut config = config.with_target(name)?;
+            override_config(&mut config);
+            Ok(config)
+        })
+        .collect()
+}
+
+/// A collection the notes are written to, with its own sync state.
+struct Sync<'a> {
+    config: &'a Config,
+    client: AnkiConnect,
+    backend: Box<dyn Backend>,
+    state: State,
+}
+
+impl<'a> Sync<'a> {
+    fn new(config: &'a Config, args: &CreateArgs) -> Result<Self> {
+        if let Some(target) = &config.target {
+            info!("writing to target {}", target.name);
+        }
+        if args.on_anki_edit.is_some() && config.journal_dir.is_none() {
+            return Err(eyre!(
+                "`--on-anki-edit` needs the journal to know the values written last"
+            ))
+            .suggestion("set `journal = true` in the config");
+        }
+        let client = AnkiConnect::new(config.anki.clone());
+        with_wait_for_anki(&client, args, || prepare_profile(&client, config, args))?;
+        let backend = with_wait_for_anki(&client, args, || create_backend(&client, args))?;
+        let state = with_wait_for_anki(&client, args, || State::new(&*backend))?;
+        Ok(Self {
+            config,
+            client,
+            backend,
+            state,
+        })
     }
 
+    fn update(&mut self, path: &Path, args: &CreateArgs) -> Result<()> {
+        if let Some(target) = &self.config.target {
+            debug!("updating target {}", target.name);
+        }
+        with_wait_for_anki(&self.client, args, || {
+            // the profile may have been switched in anki in the meantime
+            prepare_profile(&self.client, self.config, args)?;
+            update_change(&mut self.state, &*self.backend, self.config, path, args)
+        })
+    }
+}
+
+fn create(configs: &[Config], path: &Path, args: &CreateArgs) -> Result<()> {
+    for config in configs {
+        Sync::new(config, args)?.update(path, args)?;
+    }
     Ok(())
 }
 
-fn watch(config: &Config, path: &Path, args: &CreateArgs) -> Result<()> {
-    let mut state = State::new()?;
-    update_change(&mut state, config, path, args)?;
+fn watch(configs: &[Config], path: &Path, args: &CreateArgs) -> Result<()> {
+    let mut syncs = configs
+        .iter()
+        .map(|config| Sync::new(config, args))
+        .collect::<Result<Vec<_>>>()?;
+    watch_changes(path, || {
+        for sync in &mut syncs {
+            sync.update(path, args)?;
+        }
+        Ok(())
+    })
+}
+
+/// Run `on_change` now and every time `path` changes until the process is stopped.
+///
+/// Errors of `on_change` are reported without exiting.
+fn watch_changes(path: &Path, mut on_change: impl FnMut() -> Result<()>) -> Result<()> {
+    if let Err(e) = on_change() {
+        report_watch_error(&e);
+    }
 
     let (tx, rx) = std::sync::mpsc::channel();
 
@@ -520,8 +1699,8 @@ fn watch(config: &Config, path: &Path, args: &CreateArgs) -> Result<()> {
             EventKind::Create(_) => error!("file was created but should have existed before"),
             // TODO finer
             EventKind::Modify(_) => {
-                if let Err(e) = update_change(&mut state, config, path, args) {
-                    error!("{:#?}", e);
+                if let Err(e) = on_change() {
+                    report_watch_error(&e);
                 }
             }
             EventKind::Any | EventKind::Other => {
@@ -532,8 +1711,8 @@ fn watch(config: &Config, path: &Path, args: &CreateArgs) -> Result<()> {
                 watcher.watch(path, RecursiveMode::Recursive)?;
                 if !path.is_file() {
                     error!("file was removed.");
-                } else if let Err(e) = update_change(&mut state, config, path, args) {
-                    error!("{}", e);
+                } else if let Err(e) = on_change() {
+                    report_watch_error(&e);
                 }
             }
         }
@@ -544,30 +1723,135 @@ fn watch(config: &Config, path: &Path, args: &CreateArgs) -> Result<()> {
     Ok(())
 }
 
-fn get_notes(query: &str) -> Result<Vec<NoteWithInfo>> {
-    let ids = find_notes(query)?;
+/// Render the notes of `path` to html, see [`preview::write`].
+///
+/// The notes are only created in an [`InMemory`] backend with the decks and models of anki.
+/// If anki can't be reached, only the default model is available.
+fn preview(client: &AnkiConnect, config: &Config, path: &Path, args: &PreviewArgs) -> Result<()> {
+    let models = match InMemory::without_notes(client) {
+        Ok(models) => models,
+        Err(e) if api::is_unreachable(&e) => {
+            warn!("anki can't be reached, only the default model is available");
+            InMemory::default()
+        }
+        Err(e) => return Err(e),
+    };
+    if !models
+        .model_names()?
+        .iter()
+        .any(|name| name == DEFAULT_MODEL)
+    {
+        models.create_model(&default_model(DEFAULT_MODEL))?;
+    }
+    let create_args = CreateArgs {
+        theme: args.theme,
+        wait_for_anki: false,
+        // nothing is written to anki, so nothing is recorded in the journal
+        dry_run: true,
+        full_scan: false,
+        update: false,
+        on_revision: None,
+        on_anki_edit: None,
+        all_targets: false,
+        typst: args.typst.clone(),
+    };
+
+    let render = || {
+        let backend = InMemory::without_notes(&models)?;
+        let notes = query_notes(path, config)?;
+        for note in &notes {
+            backend.create_deck(&note.deck)?;
+        }
+        let sources = load_sources(path, config);
+        let theme = args.theme.or(config.target_theme()).unwrap_or(Theme::Both);
+        let output = compile(&path.to_string_lossy(), theme, &config.typst)?;
+        let files = Files {
+            sources: sources.as_ref(),
+            media_base: &SourceIndex::base(path, config.typst.root.as_deref()),
+            output: &output,
+        };
+        let mut state = State::new(&backend)?;
+        sync_notes(&mut state, &backend, config, notes, &files, &create_args)?;
+        let cards = preview::write(&backend, config, &args.out, args.watch)?;
+        info!(
+            "wrote preview of {} cards to {}",
+            cards,
+            args.out.join("index.html").display()
+        );
+        Ok(())
+    };
+
+    if args.watch {
+        watch_changes(path, render)
+    } else {
+        render()
+    }
+}
+
+/// Report an error in watch mode without exiting.
+///
+/// Errors from typst are shown as diagnostics, all other errors with their full report.
+fn report_watch_error(e: &color_eyre::Report) {
+    if let Some(typst_error) = e.downcast_ref::<TypstError>() {
+        error!(
+            "{}\n{}\nwaiting for changes at {}",
+            "-".repeat(100),
+            typst_error,
+            chrono::Local::now().format("%H:%M:%S")
+        );
+    } else {
+        error!("{:?}", e);
+    }
+}
+
+/// Get the typst id of a note from anki, stored in a tag or the id field.
+fn typst_id(config: &Config, note_info: &NoteInfo) -> Option<String> {
+    let (id_field, _) = config.id_field(&note_info.model_name);
+    tags::find_id(&note_info.tags).or_else(|| {
+        note_info
+            .fields
+            .get(id_field)
+            .map(|field| field.value.clone())
+            .filter(|id| !id.is_empty())
+    })
+}
+
+/// Get all notes matching `query`.
+///
+/// If all notes are known to be in `deck`, the card info (which contains the deck name) is not fetched.
+fn get_notes(
+    backend: &dyn Backend,
+    config: &Config,
+    query: &str,
+    deck: Option<&str>,
+) -> Result<Vec<NoteWithInfo>> {
+    let ids = backend.find_notes(query)?;
     info!("getting {} notes", ids.len());
     // don't request all notes at once, at the result is larger than 10MB
     // which ureq doesn't accept
     let notes = ids.chunks(400).try_fold(
         Vec::with_capacity(ids.len()),
         |mut acc, ids| -> color_eyre::Result<_> {
-            acc.extend(notes_info(ids)?);
+            acc.extend(backend.notes_info(ids)?);
             Ok(acc)
         },
     )?;
     debug!("got notes");
-    let card_ids = notes
-        .iter()
-        .flat_map(|note_info| note_info.cards.clone())
-        .collect::<Vec<_>>();
+    let card_ids = if deck.is_some() {
+        Vec::new()
+    } else {
+        notes
+            .iter()
+            .flat_map(|note_info| note_info.cards.clone())
+            .collect::<Vec<_>>()
+    };
     debug!("getting card info of {} cards", card_ids.len());
     // don't request all cards at once, at the result is larger than 10MB
     // which ureq doesn't accept
     let cards = card_ids.chunks(400).try_fold(
         Vec::with_capacity(ids.len()),
         |mut acc, ids| -> color_eyre::Result<_> {
-            acc.extend(cards_info(ids)?);
+            acc.extend(backend.cards_info(ids)?);
             Ok(acc)
         },
     )?;
@@ -578,13 +1862,7 @@ fn get_notes(query: &str) -> Result<Vec<NoteWithInfo>> {
     notes
         .into_iter()
         .map(|note_info| {
-            let id = note_info.fields.iter().find_map(|(name, field)| {
-                if name == "id" {
-                    Some(field.value.clone())
-                } else {
-                    None
-                }
-            });
+            let id = typst_id(config, &note_info);
             let fields = note_info
                 .fields
                 .into_iter()
@@ -597,9 +1875,15 @@ fn get_notes(query: &str) -> Result<Vec<NoteWithInfo>> {
                 })
                 .collect();
 
-            let mut deck_name = None;
+            let mut deck_name = deck.map(String::from);
             let mut question = None;
-            for _ in 0..note_info.cards.len() {
+            // without card info the deck is already known
+            let card_count = if deck.is_some() {
+                0
+            } else {
+                note_info.cards.len()
+            };
+            for _ in 0..card_count {
                 let card = cards.next().unwrap();
                 let n = card.deck_name;
                 if let Some(name) = deck_name.as_ref() {
@@ -615,6 +1899,12 @@ fn get_notes(query: &str) -> Result<Vec<NoteWithInfo>> {
                 model: note_info.model_name,
                 fields,
                 tags: note_info.tags,
+                headings: Vec::new(),
+                media: Vec::new(),
+                draft: false,
+                retired: false,
+                revision: None,
+                on_revision: None,
             };
 
             Ok(NoteWithInfo {
@@ -626,11 +1916,101 @@ fn get_notes(query: &str) -> Result<Vec<NoteWithInfo>> {
         .collect()
 }
 
-fn create_all_decks(path: &Path) -> Result<()> {
+fn print_stats(
+    backend: &dyn Backend,
+    config: &Config,
+    path: &Path,
+    args: &StatsArgs,
+) -> Result<()> {
+    let notes = query_notes(path, config)?;
+
+    let mut state = State::new(backend)?;
+    state.fetch_notes(backend, config, FetchScope::Decks, &notes)?;
+    let notes = notes
+        .into_iter()
+        .map(|note| {
+            let id = state.find_note_by_id(&note).and_then(|found| found.id);
+            (note, id)
+        })
+        .collect();
+
+    let items = stats::collect(backend, notes, args.depth)?;
+    stats::print(&items, args.format, args.top)
+}
+
+fn locate(
+    backend: &dyn Backend,
+    config: &Config,
+    path: &Path,
+    note: &str,
+    edit: bool,
+) -> Result<()> {
+    let mut id_fields = vec!["id"];
+    id_fields.extend(config.models.values().filter_map(|m| m.id_field.as_deref()));
+    id_fields.sort_unstable();
+    id_fields.dedup();
+    let query = std::iter::once(search::term("tag", &tags::id(note)))
+        .chain(id_fields.into_iter().map(|field| search::term(field, note)))
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let mut ids = backend.find_notes(&query)?;
+    if ids.is_empty() {
+        if let Ok(id) = note.parse() {
+            ids.push(id);
+        }
+    }
+    let notes = backend
+        .notes_info(&ids)
+        .with_note(|| eyre!("while getting note {}", note))?;
+    if notes.is_empty() {
+        bail!("no note with id {} found", note);
+    }
+
+    let sources = SourceIndex::load(path, config.typst.root.as_deref())?;
+    let mut first = None;
+    for note_info in notes {
+        let stored = tags::find_source(&note_info.tags).or_else(|| {
+            let field = config.source_field(&note_info.model_name)?;
+            Location::parse(&note_info.fields.get(field)?.value)
+        });
+        // the stored line is outdated after the file was changed
+        let location = typst_id(config, &note_info)
+            .and_then(|id| sources.find(&id))
+            .or(stored);
+        match location {
+            Some(location) => {
+                let file = sources.path(&location);
+                println!("{}:{}", file.display(), location.line);
+                first.get_or_insert((file, location.line));
+            }
+            None => warn!("no source location known for note {}", note_info.note_id),
+        }
+    }
+
+    if let (true, Some((file, line))) = (edit, first) {
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .with_note(|| eyre!("set `$VISUAL` or `$EDITOR` to open the location"))?;
+        let mut parts = editor.split_whitespace();
+        let program = parts.next().ok_or_else(|| eyre!("`$EDITOR` is empty"))?;
+        let status = std::process::Command::new(program)
+            .args(parts)
+            .arg(format!("+{line}"))
+            .arg(&file)
+            .status()
+            .with_note(|| eyre!("while running editor {}", editor))?;
+        if !status.success() {
+            bail!("editor {} failed with {}", editor, status);
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the decks used in `path` and apply the deck presets of the config to new decks.
+fn create_all_decks(backend: &dyn Backend, path: &Path, config: &Config) -> Result<()> {
     debug!("parsing file for used decks");
-    let path_str = path.to_string_lossy();
-    let metadata = query(&path_str)?;
-    let used_decks = metadata.0.into_iter().map(|inner| inner.value.deck);
+    let used_decks = query_notes(path, config)?.into_iter().map(|note| note.deck);
 
     let used_decks = used_decks
         .flat_map(|full| {
@@ -650,37 +2030,48 @@ fn create_all_decks(path: &Path) -> Result<()> {
         .collect::<Vec<_>>();
 
     debug!("collecting available decks from anki");
-    let available_decks: HashSet<_> = get_deck_names()?.0.into_iter().collect();
+    let available_decks: HashSet<_> = backend.deck_names()?.into_iter().collect();
 
-    let mut created: HashSet<String> = HashSet::new();
+    let mut created = Vec::new();
 
     for deck in used_decks {
         if available_decks.contains(&deck) || created.contains(&deck) {
             continue;
         }
-        if api::create_deck(&deck)?.is_some() {
+        if backend.create_deck(&deck)?.is_some() {
             info!("created deck {}", deck);
         }
-        assert!(created.insert(deck));
+        created.push(deck);
     }
 
     if created.is_empty() {
         info!("All decks were already created");
+    } else {
+        deck_options::apply(backend, config, &created)?;
     }
 
     Ok(())
 }
 
-fn create_default_model(model_name: &str) -> Result<()> {
+fn create_default_model(backend: &dyn Backend, model_name: &str) -> Result<()> {
     debug!("getting all deck names");
-    let names = get_deck_names()?;
-    if names.0.iter().any(|name| *name == model_name) {
+    let names = backend.deck_names()?;
+    if names.iter().any(|name| *name == model_name) {
         bail!("default model with name {} already exists", model_name);
     }
 
-    create_model(&CreateModelData {
+    backend.create_model(&default_model(model_name))?;
+
+    info!("created default model with name {}", model_name);
+
+    Ok(())
+}
+
+/// The default `anki-typst` model with the name `model_name`.
+fn default_model(model_name: &str) -> CreateModelData {
+    CreateModelData {
         model_name: model_name.into(),
-        in_order_fields: ["front", "back", "proof", "number", "date"]
+        in_order_fields: ["front", "back", "proof", "number", "date", "id"]
             .into_iter()
             .map(String::from)
             .collect(),
@@ -716,9 +2107,152 @@ fn create_default_model(model_name: &str) -> Result<()> {
             ]
             .map(|(a, b)| (String::from(a), String::from(b))),
         )],
-    })?;
+    }
+}
 
-    info!("created default model with name {}", model_name);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    fn note(id: &str, front: &str) -> Note {
+        Note {
+            id: Some(String::from(id)),
+            deck: String::from("Math"),
+            model: String::from(DEFAULT_MODEL),
+            fields: [("front", front), ("back", "back")]
+                .into_iter()
+                .map(|(name, value)| {
+                    let plain = String::from(value);
+                    (String::from(name), Field::Plain { plain })
+                })
+                .collect(),
+            tags: vec![String::from("example")],
+            headings: Vec::new(),
+            media: Vec::new(),
+            draft: false,
+            retired: false,
+            revision: None,
+            on_revision: None,
+        }
+    }
+
+    /// Sync `notes` like a new run of `create` with `args`.
+    fn sync(backend: &dyn Backend, config: &Config, notes: Vec<Note>, args: &[&str]) {
+        let Commands::Create(args) =
+            Args::parse_from(["anki-typst", "create"].iter().chain(args)).subcommand
+        else {
+            unreachable!();
+        };
+        let output = ThemedCompileOutput::Light(CompileOutput {
+            files: Default::default(),
+        });
+        let files = Files {
+            sources: None,
+            media_base: Path::new("."),
+            output: &output,
+        };
+        let mut state = State::new(backend).unwrap();
+        assert!(sync_notes(&mut state, backend, config, notes, &files, &args).unwrap());
+    }
+
+    /// Front field and tags of all notes, by typst id.
+    fn notes(backend: &dyn Backend) -> HashMap<String, (String, Vec<String>)> {
+        let ids = backend.find_notes("*").unwrap();
+        backend
+            .notes_info(&ids)
+            .unwrap()
+            .into_iter()
+            .map(|info| {
+                let front = info.fields["front"].value.clone();
+                (tags::find_id(&info.tags).unwrap(), (front, info.tags))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn update_round_trip() {
+        let backend = InMemory::default();
+        backend.create_deck("Math").unwrap();
+        create_default_model(&backend, DEFAULT_MODEL).unwrap();
+        let config = Config::parse("journal = false", true, None, Path::new(".")).unwrap();
+
+        let first = vec![
+            note("Pythagoras", "a² + b² = c²"),
+            note("Euler", "e^(iπ) = -1"),
+        ];
+        sync(&backend, &config, first.clone(), &[]);
+        let written = notes(&backend);
+        assert_eq!(written.len(), 2);
+        // nothing changed
+        sync(&backend, &config, first, &[]);
+        assert_eq!(notes(&backend), written);
+
+        // a tag added in anki
+        let id = backend.find_notes("tag:anki-typst::id::Euler").unwrap()[0];
+        let mut tags = written["Euler"].1.clone();
+        tags.push(String::from("mine"));
+        let tags_only = api::Note {
+            deck_name: String::from("Math"),
+            model_name: String::from(DEFAULT_MODEL),
+            fields: HashMap::new(),
+            tags,
+            audio: SingleOrMulti::default(),
+            video: SingleOrMulti::default(),
+            picture: SingleOrMulti::default(),
+        };
+        backend.update_note(id, &tags_only).unwrap();
+
+        let changed = vec![
+            note("Pythagoras", "a² + b² = c²"),
+            note("Euler", "e^(iπ) + 1 = 0"),
+        ];
+        // changed notes are only updated with `--update`
+        sync(&backend, &config, changed.clone(), &[]);
+        assert_eq!(notes(&backend)["Euler"].0, "e^(iπ) = -1");
+        sync(&backend, &config, changed, &["--update"]);
+        let updated = notes(&backend);
+        assert_eq!(updated.len(), 2);
+        assert_eq!(updated["Pythagoras"], written["Pythagoras"]);
+        let (front, tags) = &updated["Euler"];
+        assert_eq!(front, "e^(iπ) + 1 = 0");
+        assert!(tags.contains(&String::from("mine")), "{tags:?}");
+        assert_ne!(tags::find_hash(tags), tags::find_hash(&written["Euler"].1));
+    }
+
+    #[test]
+    fn suspend_without_update() {
+        let backend = InMemory::default();
+        backend.create_deck("Math").unwrap();
+        create_default_model(&backend, DEFAULT_MODEL).unwrap();
+        let config = Config::parse("journal = false", true, None, Path::new(".")).unwrap();
+        sync(&backend, &config, vec![note("Euler", "e^(iπ) = -1")], &[]);
+        let id = backend.find_notes("tag:anki-typst::id::Euler").unwrap()[0];
+        let suspended = || {
+            let cards = backend.notes_info(&[id]).unwrap().remove(0).cards;
+            backend.are_suspended(&cards).unwrap()
+        };
+        assert_eq!(suspended(), [Some(false)]);
+
+        // the content changed too, but it is only updated with `--update`
+        let mut draft = note("Euler", "e^(iπ) + 1 = 0");
+        draft.draft = true;
+        draft.revision = Some(String::from("1"));
+        sync(&backend, &config, vec![draft], &[]);
+        assert_eq!(suspended(), [Some(true)]);
+        let (front, tags) = &notes(&backend)["Euler"];
+        assert_eq!(front, "e^(iπ) = -1");
+        assert!(tags.contains(&String::from(tags::SUSPENDED)), "{tags:?}");
+        assert_eq!(tags::find_revision(tags).as_deref(), Some("1"));
+
+        sync(
+            &backend,
+            &config,
+            vec![note("Euler", "e^(iπ) + 1 = 0")],
+            &[],
+        );
+        assert_eq!(suspended(), [Some(false)]);
+        let (front, tags) = &notes(&backend)["Euler"];
+        assert_eq!(front, "e^(iπ) = -1");
+        assert!(!tags.contains(&String::from(tags::SUSPENDED)), "{tags:?}");
+    }
 }
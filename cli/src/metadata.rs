@@ -1,7 +1,12 @@
 use indexmap::IndexMap;
 use serde::de::Error;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::Formatter;
+use std::hash::{Hash, Hasher};
+
+use crate::config::RevisionPolicy;
+use crate::{tags, template};
 
 const EXPORT_LABEL: &str = "<anki-export>";
 
@@ -42,7 +47,7 @@ impl<'de> Deserialize<'de> for InnerMetadata {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Note {
     // may be [`None`] for notes coming from anki
     pub(crate) id: Option<String>,
@@ -50,30 +55,210 @@ pub struct Note {
     pub(crate) model: String,
     pub(crate) fields: IndexMap<String, Field>,
     pub(crate) tags: Vec<String>,
+    /// Bodies of the headings containing the note, empty for notes coming from anki
+    #[serde(default)]
+    pub(crate) headings: Vec<String>,
+    /// Files attached to the note, empty for notes coming from anki
+    #[serde(default)]
+    pub(crate) media: Vec<Media>,
+    /// The note isn't ready yet
+    #[serde(default)]
+    pub(crate) draft: bool,
+    /// The note isn't relevant anymore
+    #[serde(default)]
+    pub(crate) retired: bool,
+    /// Changed in typst to mark a major edit
+    #[serde(default)]
+    pub(crate) revision: Option<String>,
+    /// What happens to the cards when `revision` changes
+    #[serde(default)]
+    pub(crate) on_revision: Option<RevisionPolicy>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+impl Note {
+    /// Whether the cards of this note should be suspended.
+    pub fn suspended(&self) -> bool {
+        self.draft || self.retired
+    }
+
+    /// Fingerprint of the content of this note.
+    ///
+    /// Two notes with the same fingerprint are considered the same note.
+    /// The fingerprint covers
+    /// - the deck and model name
+    /// - the tags, in order, except the ones added by anki-typst (see [`tags::is_generated`])
+    /// - the name and value of all non-empty raw and plain fields, independent of their order
+    ///
+    /// The id and fields rendered as images are not part of the fingerprint,
+    /// since notes coming from anki only have the rendered html for them.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_fingerprint(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Feed everything covered by [`Note::fingerprint`] into `hasher`.
+    pub fn hash_fingerprint(&self, hasher: &mut impl Hasher) {
+        let mut fields = self
+            .fields
+            .iter()
+            .filter_map(|(name, value)| Some((name, value.text()?)))
+            .filter(|(_, value)| !value.is_empty())
+            .collect::<Vec<_>>();
+        fields.sort_unstable();
+
+        self.deck.hash(hasher);
+        self.model.hash(hasher);
+        for tag in self.tags.iter().filter(|tag| !tags::is_generated(tag)) {
+            tag.hash(hasher);
+        }
+        fields.hash(hasher);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum Field {
     Raw(String),
+    /// Plain text with math converted for MathJax
+    Math {
+        plain: String,
+        mathjax: String,
+    },
     Plain {
         plain: String,
     },
     Content {
         content: String,
+        /// Searchable text of the content, missing for older versions of the typst package
+        #[serde(default)]
+        text: Option<String>,
         page_start: usize,
         page_end: usize,
     },
     Empty,
 }
 
+impl Field {
+    /// The text written to anki for fields which aren't rendered as images.
+    pub fn text(&self) -> Option<String> {
+        match self {
+            Self::Raw(value) | Self::Plain { plain: value } => Some(value.clone()),
+            Self::Math { mathjax, .. } => Some(template::escape_html(mathjax)),
+            Self::Content { .. } | Self::Empty => None,
+        }
+    }
+
+    /// Text which makes fields rendered as images searchable.
+    pub fn searchable_text(&self) -> Option<&str> {
+        match self {
+            Self::Content { content, text, .. } => Some(text.as_deref().unwrap_or(content).trim()),
+            Self::Raw(_) | Self::Plain { .. } | Self::Math { .. } | Self::Empty => None,
+        }
+    }
+}
+
 impl std::fmt::Display for Field {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
             Self::Raw(val) => val,
-            Self::Plain { plain } => plain,
+            Self::Plain { plain } | Self::Math { plain, .. } => plain,
             Self::Content { content, .. } => content,
             Self::Empty => "",
         })
     }
 }
+
+/// An audio, video or picture file attached to a note.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Media {
+    /// Path relative to the typst root
+    pub path: String,
+    /// Fields to which the file is appended
+    #[serde(default)]
+    pub fields: Vec<String>,
+    #[serde(default)]
+    kind: Option<MediaKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Audio,
+    Video,
+    Picture,
+}
+
+impl Media {
+    /// The given kind or the one guessed from the file extension.
+    pub fn kind(&self) -> Option<MediaKind> {
+        if self.kind.is_some() {
+            return self.kind;
+        }
+        let extension = std::path::Path::new(&self.path)
+            .extension()?
+            .to_string_lossy()
+            .to_lowercase();
+        match extension.as_str() {
+            "mp3" | "ogg" | "oga" | "opus" | "wav" | "flac" | "m4a" | "aac" => {
+                Some(MediaKind::Audio)
+            }
+            "mp4" | "webm" | "mkv" | "mov" | "avi" | "ogv" => Some(MediaKind::Video),
+            "png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "bmp" | "avif" => {
+                Some(MediaKind::Picture)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(tags: &[&str], front: &str) -> Note {
+        Note {
+            id: Some(String::from("Pythagoras")),
+            deck: String::from("Math"),
+            model: String::from("anki-typst"),
+            fields: IndexMap::from([
+                (String::from("front"), Field::Raw(String::from(front))),
+                (String::from("back"), Field::Empty),
+            ]),
+            tags: tags.iter().map(|tag| String::from(*tag)).collect(),
+            headings: Vec::new(),
+            media: Vec::new(),
+            draft: false,
+            retired: false,
+            revision: None,
+            on_revision: None,
+        }
+    }
+
+    #[test]
+    fn fingerprint_ignores_generated_tags() {
+        let typst = note(&["example"], "Pythagoras");
+        let anki = note(
+            &[
+                "example",
+                tags::GENERATED,
+                &tags::date("2024-01-01"),
+                &tags::id("Pythagoras"),
+                &tags::hash(42),
+                tags::SUSPENDED,
+            ],
+            "Pythagoras",
+        );
+        assert_eq!(typst.fingerprint(), anki.fingerprint());
+    }
+
+    #[test]
+    fn fingerprint_covers_fields_and_tags() {
+        let fingerprint = note(&["example"], "Pythagoras").fingerprint();
+        assert_ne!(
+            fingerprint,
+            note(&["example"], "Pythagoras theorem").fingerprint()
+        );
+        assert_ne!(fingerprint, note(&["theorem"], "Pythagoras").fingerprint());
+    }
+}
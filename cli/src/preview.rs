@@ -0,0 +1,214 @@
+//! Static html preview of the cards of a typst file.
+//!
+//! The cards are rendered by an [`InMemory`] backend with the card templates and css of their model.
+//! Each side is shown in light and night mode, in its own `<iframe>` so the css doesn't leak.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use base64::Engine;
+use color_eyre::eyre::eyre;
+use color_eyre::{Help, Result};
+use tracing::{debug, warn};
+
+use crate::api::{CardInfo, MediaDataInner};
+use crate::backend::{Backend, InMemory};
+use crate::config::Config;
+use crate::template::escape_html;
+
+/// Css which is applied before the css of the model, similar to the one of anki.
+const BASE_CSS: &str = "
+body { margin: 0; padding: 1em; }
+body.nightMode { color: #fcfcfc; background-color: #2c2c2c; }
+img { max-width: 100%; }
+";
+
+const PAGE_CSS: &str = "
+body { font-family: sans-serif; margin: 1em 2em; }
+section { margin-bottom: 2em; }
+.sides { display: grid; grid-template-columns: repeat(2, 1fr); gap: 1em; }
+figure { margin: 0; }
+figcaption { color: #666; font-size: 0.9em; }
+iframe { width: 100%; height: 250px; border: 1px solid #aaa; resize: vertical; }
+";
+
+const MATHJAX: &str =
+    r#"<script async src="https://cdn.jsdelivr.net/npm/mathjax@3/es5/tex-chtml.js"></script>"#;
+
+/// Write `index.html` and the media files of all cards in `backend` to `out`.
+///
+/// With `live_reload` the page reloads itself when it is written again.
+/// Returns the number of cards.
+pub fn write(backend: &InMemory, config: &Config, out: &Path, live_reload: bool) -> Result<usize> {
+    std::fs::create_dir_all(out)
+        .with_note(|| eyre!("while creating directory {}", out.display()))?;
+
+    for (filename, data) in backend.media() {
+        if Path::new(&filename).file_name() != Some(filename.as_ref()) {
+            warn!("not writing media file with invalid name {}", filename);
+            continue;
+        }
+        let path = out.join(&filename);
+        match data {
+            MediaDataInner::Data(data) => {
+                let data = base64::engine::general_purpose::STANDARD.decode(data)?;
+                std::fs::write(&path, data)
+            }
+            MediaDataInner::Path(source) => std::fs::copy(source, &path).map(|_| ()),
+            MediaDataInner::Url(url) => {
+                debug!("not downloading media file {} from {}", filename, url);
+                continue;
+            }
+        }
+        .with_note(|| eyre!("while writing media file {}", path.display()))?;
+    }
+
+    let notes = backend.notes_info(&backend.find_notes("*")?)?;
+    let card_ids = notes
+        .iter()
+        .flat_map(|note| note.cards.clone())
+        .collect::<Vec<_>>();
+    let cards = backend.cards_info(&card_ids)?;
+    let titles = notes
+        .iter()
+        .map(|note| {
+            let title =
+                crate::typst_id(config, note).unwrap_or_else(|| format!("note {}", note.note_id));
+            (note.note_id, title)
+        })
+        .collect::<HashMap<_, _>>();
+    let mut template_names = HashMap::new();
+
+    let mut sections = String::new();
+    for card in &cards {
+        if !template_names.contains_key(&card.model_name) {
+            let names = backend
+                .model_templates(&card.model_name)?
+                .into_keys()
+                .collect::<Vec<_>>();
+            template_names.insert(card.model_name.clone(), names);
+        }
+        let template = usize::try_from(card.ord)
+            .ok()
+            .and_then(|ord| template_names[&card.model_name].get(ord))
+            .cloned()
+            .unwrap_or_default();
+        sections.push_str(&card_section(
+            card,
+            titles
+                .get(&card.note)
+                .map(String::as_str)
+                .unwrap_or_default(),
+            &template,
+        ));
+    }
+
+    let version = fasthash::metro::hash64(&sections);
+    let reload = if live_reload {
+        format!(
+            r#"<script>
+const version = "{version}";
+setInterval(() => {{
+  const script = document.createElement("script");
+  script.src = "version.js?" + Date.now();
+  script.onload = () => script.remove();
+  document.head.append(script);
+}}, 1000);
+</script>"#
+        )
+    } else {
+        String::new()
+    };
+    let index = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>anki-typst preview</title>
+<style>{PAGE_CSS}</style>
+{reload}
+</head>
+<body>
+<h1>{} cards</h1>
+{sections}
+</body>
+</html>
+"#,
+        cards.len()
+    );
+
+    let index_path = out.join("index.html");
+    std::fs::write(&index_path, index)
+        .with_note(|| eyre!("while writing {}", index_path.display()))?;
+    if live_reload {
+        let version_path = out.join("version.js");
+        std::fs::write(
+            &version_path,
+            format!("if (version !== \"{version}\") location.reload();\n"),
+        )
+        .with_note(|| eyre!("while writing {}", version_path.display()))?;
+    }
+
+    Ok(cards.len())
+}
+
+fn card_section(card: &CardInfo, title: &str, template: &str) -> String {
+    let sides = [
+        ("Front", &card.question, false),
+        ("Back", &card.answer, false),
+        ("Front, night mode", &card.question, true),
+        ("Back, night mode", &card.answer, true),
+    ]
+    .into_iter()
+    .map(|(caption, html, night)| {
+        let document = side_document(html, &card.css, card.ord + 1, night);
+        format!(
+            "<figure><figcaption>{caption}</figcaption><iframe srcdoc=\"{}\"></iframe></figure>\n",
+            escape_html(&document)
+        )
+    })
+    .collect::<String>();
+
+    format!(
+        "<section>\n<h2>{} <small>({})</small></h2>\n<p>deck {}, model {}</p>\n<div class=\"sides\">\n{sides}</div>\n</section>\n",
+        escape_html(title),
+        escape_html(template),
+        escape_html(&card.deck_name),
+        escape_html(&card.model_name),
+    )
+}
+
+/// A complete html document for one side of a card, like anki shows it.
+fn side_document(html: &str, css: &str, card: i32, night: bool) -> String {
+    let night = if night { " nightMode night_mode" } else { "" };
+    let mathjax = if html.contains("\\(") || html.contains("\\[") {
+        MATHJAX
+    } else {
+        ""
+    };
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><style>{BASE_CSS}{css}</style>{mathjax}</head>\
+         <body class=\"card card{card}{night}\"><div id=\"qa\">{}</div></body></html>",
+        replace_sounds(html)
+    )
+}
+
+/// Replace anki's `[sound:filename]` with an audio player.
+fn replace_sounds(html: &str) -> String {
+    let mut res = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("[sound:") {
+        let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+            break;
+        };
+        res.push_str(&rest[..start]);
+        let filename = &rest[start + "[sound:".len()..end];
+        res.push_str(&format!(
+            "<audio controls src=\"{}\"></audio>",
+            escape_html(filename)
+        ));
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+    res
+}
@@ -0,0 +1,119 @@
+//! Declarative rules from the `rewrite` section of the config which change the notes.
+//!
+//! The deck rules are applied first, then the tag and field rules,
+//! so conditions on the deck see the renamed deck.
+//! Within each kind the rules are applied in the order of the config.
+
+use tracing::{debug, warn};
+
+use crate::config::{Config, FieldMapping, NoteFilter, Rewrite};
+use crate::metadata::Note;
+
+impl NoteFilter {
+    pub fn matches(&self, note: &Note) -> bool {
+        let id_matches = match (&self.id, &note.id) {
+            (Some(re), Some(id)) => re.is_match(id),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        id_matches
+            && self.deck.as_ref().is_none_or(|re| re.is_match(&note.deck))
+            && self
+                .model
+                .as_ref()
+                .is_none_or(|re| re.is_match(&note.model))
+    }
+}
+
+/// Rename or drop the fields of `note` by the `field_map` of its model.
+///
+/// Fields which aren't in the map keep their name.
+pub fn map_fields(config: &Config, note: &mut Note) {
+    let Some(field_map) = config.field_map(&note.model) else {
+        return;
+    };
+    let map = |name: String| match field_map.get(&name) {
+        Some(FieldMapping::Field(field)) => Some(field.clone()),
+        Some(FieldMapping::Keep(false)) => None,
+        Some(FieldMapping::Keep(true)) | None => Some(name),
+    };
+    note.fields = std::mem::take(&mut note.fields)
+        .into_iter()
+        .filter_map(|(name, value)| Some((map(name)?, value)))
+        .collect();
+    for media in &mut note.media {
+        media.fields = std::mem::take(&mut media.fields)
+            .into_iter()
+            .filter_map(map)
+            .collect();
+    }
+}
+
+/// Apply all rules of `rewrite` to `note`.
+pub fn apply(rewrite: &Rewrite, note: &mut Note) {
+    for rule in &rewrite.decks {
+        if rule.filter.matches(note) {
+            note.deck = rule.pattern.replace_all(&note.deck, &rule.replace);
+        }
+    }
+
+    for rule in &rewrite.tags {
+        if !rule.filter.matches(note) {
+            continue;
+        }
+        note.tags
+            .retain(|tag| !rule.remove.iter().any(|re| re.is_match(tag)));
+        for tag in &mut note.tags {
+            if let Some(new) = rule.rename.get(tag) {
+                *tag = new.clone();
+            }
+        }
+        for tag in &rule.add {
+            if !note.tags.contains(tag) {
+                note.tags.push(tag.clone());
+            }
+        }
+        // renaming may lead to duplicates
+        let mut seen = Vec::with_capacity(note.tags.len());
+        note.tags.retain(|tag| {
+            let new = !seen.contains(tag);
+            if new {
+                seen.push(tag.clone());
+            }
+            new
+        });
+    }
+
+    for rule in &rewrite.fields {
+        if !rule.filter.matches(note) {
+            continue;
+        }
+        let rename = |name: &String| rule.rename.get(name).cloned();
+        let count = note.fields.len();
+        // keep the order of the fields
+        note.fields = std::mem::take(&mut note.fields)
+            .into_iter()
+            .map(|(name, value)| (rename(&name).unwrap_or(name), value))
+            .collect();
+        if note.fields.len() != count {
+            warn!(
+                "renaming the fields of note {} merged some of them, only the last value is kept",
+                note.id.as_deref().unwrap_or_default()
+            );
+        }
+        for media in &mut note.media {
+            for field in &mut media.fields {
+                if let Some(new) = rename(field) {
+                    *field = new;
+                }
+            }
+        }
+    }
+
+    debug!(
+        "rewrote note {} to deck {} with tags {:?}",
+        note.id.as_deref().unwrap_or_default(),
+        note.deck,
+        note.tags
+    );
+}
@@ -0,0 +1,197 @@
+//! Locations of notes in the typst sources.
+//!
+//! `typst query` doesn't report where an element was created,
+//! so notes are located by searching their id as a string literal in the typst files.
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::eyre;
+use color_eyre::{Help, Result};
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Path relative to the base directory of the [`SourceIndex`]
+    pub file: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+}
+
+impl Location {
+    /// Parse a location in the form `file:line`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (file, line) = value.rsplit_once(':')?;
+        Some(Self {
+            file: PathBuf::from(file),
+            line: line.parse().ok()?,
+        })
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// Contents of all typst files of a project.
+#[derive(Debug)]
+pub struct SourceIndex {
+    base: PathBuf,
+    /// Path relative to `base` and content, the main file first
+    files: Vec<(PathBuf, String)>,
+}
+
+impl SourceIndex {
+    /// Directory which contains the sources: the typst root or the directory of `main`.
+    pub fn base(main: &Path, root: Option<&Path>) -> PathBuf {
+        match root {
+            Some(root) => root.to_path_buf(),
+            None if main.is_dir() => main.to_path_buf(),
+            None => match main.parent() {
+                Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            },
+        }
+    }
+
+    /// Read all typst files below the base directory, see [`SourceIndex::base`].
+    ///
+    /// Files which can't be read are skipped with a warning.
+    pub fn load(main: &Path, root: Option<&Path>) -> Result<Self> {
+        let base = Self::base(main, root);
+        let mut paths = Vec::new();
+        collect_typst_files(&base, &mut paths)?;
+        // prefer matches in the main file
+        if let Some(index) = paths.iter().position(|path| same_file(path, main)) {
+            let main = paths.remove(index);
+            paths.insert(0, main);
+        }
+
+        let files = paths
+            .into_iter()
+            .filter_map(|path| {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|e| {
+                        warn!(
+                            "skipping {} for the source locations: {}",
+                            path.display(),
+                            e
+                        )
+                    })
+                    .ok()?;
+                let relative = path.strip_prefix(&base).unwrap_or(&path).to_path_buf();
+                Some((relative, content))
+            })
+            .collect::<Vec<_>>();
+        debug!("indexed {} typst files in {}", files.len(), base.display());
+
+        Ok(Self { base, files })
+    }
+
+    /// Find the first line which contains `id` as a string literal.
+    pub fn find(&self, id: &str) -> Option<Location> {
+        let literal = string_literal(id);
+        self.files.iter().find_map(|(file, content)| {
+            let index = content.lines().position(|line| line.contains(&literal))?;
+            Some(Location {
+                file: file.clone(),
+                line: index + 1,
+            })
+        })
+    }
+
+    /// Path of the file of `location` including the base directory.
+    pub fn path(&self, location: &Location) -> PathBuf {
+        self.base.join(&location.file)
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn collect_typst_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .with_note(|| eyre!("while reading directory {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_typst_files(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "typ") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// `value` as it is written as a typst string.
+fn string_literal(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(files: &[(&str, &str)]) -> SourceIndex {
+        SourceIndex {
+            base: PathBuf::from("notes"),
+            files: files
+                .iter()
+                .map(|(path, content)| (PathBuf::from(path), String::from(*content)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn parse_location() {
+        let location = Location::parse("chapters/a:b.typ:12").unwrap();
+        assert_eq!(location.file, Path::new("chapters/a:b.typ"));
+        assert_eq!(location.line, 12);
+        assert_eq!(location.to_string(), "chapters/a:b.typ:12");
+        assert_eq!(Location::parse("main.typ"), None);
+        assert_eq!(Location::parse("main.typ:x"), None);
+    }
+
+    #[test]
+    fn escape_literal() {
+        assert_eq!(string_literal("Pythagoras"), "\"Pythagoras\"");
+        assert_eq!(string_literal("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(string_literal("a\\b"), "\"a\\\\b\"");
+    }
+
+    #[test]
+    fn find_first_literal() {
+        let index = index(&[
+            (
+                "main.typ",
+                "#import \"chapter.typ\"\n#theorem(id: \"Euler\")[]",
+            ),
+            (
+                "chapter.typ",
+                "// Pythagoras\n\n#theorem(id: \"Pythagoras\")[]",
+            ),
+            ("other.typ", "#theorem(id: \"Pythagoras\")[]"),
+        ]);
+        let euler = index.find("Euler").unwrap();
+        assert_eq!(euler.to_string(), "main.typ:2");
+        // only string literals match, the files are searched in order
+        let pythagoras = index.find("Pythagoras").unwrap();
+        assert_eq!(pythagoras.to_string(), "chapter.typ:3");
+        assert_eq!(index.path(&pythagoras), Path::new("notes/chapter.typ"));
+        assert_eq!(index.find("Gauss"), None);
+    }
+}
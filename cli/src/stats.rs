@@ -0,0 +1,322 @@
+//! Review statistics of the notes in a typst file.
+//!
+//! The notes are joined with the review data of their cards in anki
+//! and summarized per section of the typst file.
+
+use std::collections::BTreeMap;
+
+use color_eyre::Result;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::backend::Backend;
+use crate::metadata::{Field, Note};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Human readable summary
+    Text,
+    /// One row per note
+    Csv,
+    /// Sections and notes
+    Json,
+}
+
+/// Review data of a single typst note.
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemStats {
+    pub id: Option<String>,
+    pub number: Option<String>,
+    pub deck: String,
+    pub section: String,
+    /// Anki id, `None` if the note is not in anki
+    pub note_id: Option<usize>,
+    pub cards: usize,
+    pub reps: i32,
+    pub lapses: i32,
+    /// Average interval in days of the reviewed cards
+    pub average_interval: Option<f64>,
+}
+
+impl ItemStats {
+    fn reviewed(&self) -> bool {
+        self.reps > 0
+    }
+
+    fn name(&self) -> String {
+        let id = self.id.as_deref().unwrap_or("<no id>");
+        match &self.number {
+            Some(number) => format!("{id} ({number})"),
+            None => String::from(id),
+        }
+    }
+}
+
+/// Summary of all notes in a section.
+#[derive(Debug, Clone, Serialize)]
+pub struct SectionStats {
+    pub section: String,
+    pub items: usize,
+    pub missing: usize,
+    pub never_reviewed: usize,
+    pub lapses: i32,
+    /// Average interval in days of the reviewed cards
+    pub average_interval: Option<f64>,
+}
+
+/// Intervals of all reviewed cards, negative intervals are in seconds and count as 0 days.
+fn reviewed_intervals(cards: &[crate::api::CardInfo]) -> impl Iterator<Item = f64> + '_ {
+    cards
+        .iter()
+        .filter(|card| card.reps > 0)
+        .map(|card| f64::from(card.interval.max(0)))
+}
+
+fn average(values: impl IntoIterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values
+        .into_iter()
+        .fold((0.0, 0_u32), |(sum, count), value| (sum + value, count + 1));
+    (count != 0).then(|| sum / f64::from(count))
+}
+
+/// Name of the section of `note`, the first `depth` headings joined with `::`.
+///
+/// Falls back to the deck name for notes without headings.
+fn section(note: &Note, depth: usize) -> String {
+    if note.headings.is_empty() {
+        note.deck
+            .split("::")
+            .take(depth)
+            .collect::<Vec<_>>()
+            .join("::")
+    } else {
+        note.headings
+            .iter()
+            .take(depth)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("::")
+    }
+}
+
+/// Collect the review data for `notes`, each with its anki id if it exists in anki.
+pub fn collect(
+    backend: &dyn Backend,
+    notes: Vec<(Note, Option<usize>)>,
+    depth: usize,
+) -> Result<Vec<ItemStats>> {
+    let note_ids = notes.iter().filter_map(|(_, id)| *id).collect::<Vec<_>>();
+    debug!("getting cards of {} notes", note_ids.len());
+    let mut card_ids = BTreeMap::new();
+    for ids in note_ids.chunks(400) {
+        for info in backend.notes_info(ids)? {
+            card_ids.insert(info.note_id, info.cards);
+        }
+    }
+    let mut cards = BTreeMap::<usize, Vec<_>>::new();
+    let all_card_ids = card_ids.values().flatten().copied().collect::<Vec<_>>();
+    for ids in all_card_ids.chunks(400) {
+        for card in backend.cards_info(ids)? {
+            cards.entry(card.note).or_default().push(card);
+        }
+    }
+
+    Ok(notes
+        .into_iter()
+        .map(|(note, note_id)| {
+            let cards = note_id
+                .and_then(|id| cards.get(&id))
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let number = note.fields.get("number").and_then(|field| match field {
+                Field::Raw(value)
+                | Field::Plain { plain: value }
+                | Field::Math { plain: value, .. } => Some(value.clone()),
+                Field::Content { .. } | Field::Empty => None,
+            });
+            ItemStats {
+                section: section(&note, depth),
+                id: note.id,
+                number,
+                deck: note.deck,
+                note_id,
+                cards: cards.len(),
+                reps: cards.iter().map(|card| card.reps).sum(),
+                lapses: cards.iter().map(|card| card.lapses).sum(),
+                average_interval: average(reviewed_intervals(cards)),
+            }
+        })
+        .collect())
+}
+
+/// Summarize `items` per section, in the order the sections appear.
+pub fn sections(items: &[ItemStats]) -> Vec<SectionStats> {
+    let mut sections: Vec<(SectionStats, Vec<f64>)> = Vec::new();
+    for item in items {
+        let index = match sections.iter().position(|(s, _)| s.section == item.section) {
+            Some(index) => index,
+            None => {
+                sections.push((
+                    SectionStats {
+                        section: item.section.clone(),
+                        items: 0,
+                        missing: 0,
+                        never_reviewed: 0,
+                        lapses: 0,
+                        average_interval: None,
+                    },
+                    Vec::new(),
+                ));
+                sections.len() - 1
+            }
+        };
+        let (section, intervals) = &mut sections[index];
+        section.items += 1;
+        section.lapses += item.lapses;
+        if item.note_id.is_none() {
+            section.missing += 1;
+        } else if !item.reviewed() {
+            section.never_reviewed += 1;
+        }
+        intervals.extend(item.average_interval);
+    }
+
+    sections
+        .into_iter()
+        .map(|(mut section, intervals)| {
+            section.average_interval = average(intervals);
+            section
+        })
+        .collect()
+}
+
+/// Print the statistics to stdout.
+///
+/// `top` limits the number of hardest notes in the text output.
+pub fn print(items: &[ItemStats], format: Format, top: usize) -> Result<()> {
+    match format {
+        Format::Text => print_text(items, top),
+        Format::Csv => print_csv(items),
+        Format::Json => {
+            #[derive(Serialize)]
+            struct Report<'a> {
+                sections: Vec<SectionStats>,
+                items: &'a [ItemStats],
+            }
+
+            let report = Report {
+                sections: sections(items),
+                items,
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn format_interval(interval: Option<f64>) -> String {
+    match interval {
+        Some(interval) => format!("{interval:.1} days"),
+        None => String::from("-"),
+    }
+}
+
+fn print_text(items: &[ItemStats], top: usize) {
+    let sections = sections(items);
+    let width = sections
+        .iter()
+        .map(|section| section.section.len())
+        .max()
+        .unwrap_or_default()
+        .max("section".len());
+
+    println!(
+        "{:width$}  {:>5}  {:>7}  {:>14}  {:>6}  {:>12}",
+        "section", "items", "missing", "never reviewed", "lapses", "avg interval"
+    );
+    for section in &sections {
+        println!(
+            "{:width$}  {:>5}  {:>7}  {:>14}  {:>6}  {:>12}",
+            section.section,
+            section.items,
+            section.missing,
+            section.never_reviewed,
+            section.lapses,
+            format_interval(section.average_interval)
+        );
+    }
+
+    let mut hardest = items
+        .iter()
+        .filter(|item| item.lapses > 0)
+        .collect::<Vec<_>>();
+    hardest.sort_by_key(|item| std::cmp::Reverse(item.lapses));
+    if !hardest.is_empty() {
+        println!("\nHardest notes:");
+        for item in hardest.into_iter().take(top) {
+            println!(
+                "  {:>4} lapses  {} in {}",
+                item.lapses,
+                item.name(),
+                item.section
+            );
+        }
+    }
+
+    let never_reviewed = items
+        .iter()
+        .filter(|item| item.note_id.is_some() && !item.reviewed())
+        .collect::<Vec<_>>();
+    if !never_reviewed.is_empty() {
+        println!("\nNever reviewed:");
+        for item in never_reviewed {
+            println!("  {} in {}", item.name(), item.section);
+        }
+    }
+
+    let missing = items
+        .iter()
+        .filter(|item| item.note_id.is_none())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        println!("\nNot in anki:");
+        for item in missing {
+            println!("  {} in {}", item.name(), item.section);
+        }
+    }
+}
+
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
+
+fn print_csv(items: &[ItemStats]) {
+    println!("id,number,deck,section,note_id,cards,reps,lapses,average_interval");
+    for item in items {
+        let row = [
+            item.id.clone().unwrap_or_default(),
+            item.number.clone().unwrap_or_default(),
+            item.deck.clone(),
+            item.section.clone(),
+            item.note_id.map(|id| id.to_string()).unwrap_or_default(),
+            item.cards.to_string(),
+            item.reps.to_string(),
+            item.lapses.to_string(),
+            item.average_interval
+                .map(|interval| format!("{interval:.1}"))
+                .unwrap_or_default(),
+        ];
+        println!(
+            "{}",
+            row.iter()
+                .map(|value| csv_value(value))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+    }
+}
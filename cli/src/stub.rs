@@ -0,0 +1,539 @@
+//! A local server which speaks the AnkiConnect protocol.
+//!
+//! This is meant for testing without a running Anki.
+//! Everything is stored in an [`InMemory`] backend.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::{Help, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, warn};
+
+use crate::api::{CreateModelData, DeckConfig, MediaData, Note};
+use crate::backend::{Backend, InMemory};
+
+/// Actions which don't change the collection, so it doesn't need to be saved afterwards.
+const READ_ONLY_ACTIONS: [&str; 14] = [
+    "version",
+    "deckNames",
+    "getDeckConfig",
+    "modelNames",
+    "modelFieldNames",
+    "modelTemplates",
+    "modelStyling",
+    "findNotes",
+    "notesInfo",
+    "cardsInfo",
+    "canAddNotes",
+    "areSuspended",
+    "retrieveMediaFile",
+    "getActiveProfile",
+];
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    action: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    version: Option<u32>,
+}
+
+/// Serve the AnkiConnect API at `port` until the process is stopped.
+///
+/// If `state` is given, the collection is loaded from and saved to this file.
+/// If `profiles` are given, they replace the known profiles and the first one is active.
+pub fn serve(port: u16, state: Option<&Path>, profiles: Vec<String>) -> Result<()> {
+    let backend = match state {
+        Some(path) if path.is_file() => InMemory::load(path)?,
+        _ => InMemory::default(),
+    };
+    if !profiles.is_empty() {
+        backend.set_profiles(profiles);
+    }
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .with_note(|| eyre!("while binding to port {}", port))?;
+    info!("serving AnkiConnect stub at http://localhost:{}", port);
+    accept(&listener, &backend, state);
+
+    Ok(())
+}
+
+/// Answer all requests to `listener` with `backend`.
+fn accept(listener: &TcpListener, backend: &InMemory, state: Option<&Path>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle_connection(backend, stream, state) {
+            error!("{:?}", e);
+        }
+    }
+}
+
+fn handle_connection(
+    backend: &InMemory,
+    mut stream: TcpStream,
+    state: Option<&Path>,
+) -> Result<()> {
+    let mut reader = BufReader::new(&mut stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    debug!("got request {}", request_line.trim());
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse()?;
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let response = if request_line.starts_with("POST") {
+        let (response, changed) = handle_request(backend, &body);
+        if let (Some(path), true) = (state, changed) {
+            backend.save(path)?;
+        }
+        response
+    } else {
+        // like anki-connect, answer other requests with a short description
+        json!("AnkiConnect v.6 (anki-typst stub)")
+    };
+
+    let response = serde_json::to_string(&response)?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.len(),
+        response
+    )?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Returns the response and whether the collection may have changed.
+fn handle_request(backend: &InMemory, body: &[u8]) -> (Value, bool) {
+    let request: Request = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => {
+            let response = json!({ "result": null, "error": format!("invalid request: {e}") });
+            return (response, false);
+        }
+    };
+    let version = request.version.unwrap_or(4);
+    let changed = !READ_ONLY_ACTIONS.contains(&request.action.as_str());
+    if changed {
+        info!("handling action {}", request.action);
+    } else {
+        debug!("handling action {}", request.action);
+    }
+    let result = if request.action == "multi" {
+        multi(backend, request.params)
+    } else {
+        dispatch(backend, &request.action, request.params)
+    };
+
+    (respond(result, version), changed)
+}
+
+/// Wrap the result like anki-connect does.
+///
+/// Up to version 4 only the result is returned, errors are always returned with `error` set.
+fn respond(result: Result<Value>, version: u32) -> Value {
+    match result {
+        Ok(result) if version <= 4 => result,
+        Ok(result) => json!({ "result": result, "error": null }),
+        Err(e) => json!({ "result": null, "error": e.to_string() }),
+    }
+}
+
+fn multi(backend: &InMemory, params: Value) -> Result<Value> {
+    #[derive(Debug, Deserialize)]
+    struct Params {
+        actions: Vec<Request>,
+    }
+
+    let params: Params = parse_params(params)?;
+    Ok(Value::Array(
+        params
+            .actions
+            .into_iter()
+            .map(|request| {
+                // like anki-connect, the actions don't inherit the version of `multi`
+                let version = request.version.unwrap_or(4);
+                respond(dispatch(backend, &request.action, request.params), version)
+            })
+            .collect(),
+    ))
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T> {
+    serde_path_to_error::deserialize(params)
+        .map_err(|e| eyre!("invalid params at {}: {}", e.path(), e.inner()))
+}
+
+fn dispatch(backend: &InMemory, action: &str, params: Value) -> Result<Value> {
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct DeckParams {
+        deck: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct DeckConfigParams {
+        config: DeckConfig,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct DeckConfigIdParams {
+        decks: Vec<String>,
+        config_id: usize,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CloneDeckConfigParams {
+        name: String,
+        clone_from: usize,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct FilenameParams {
+        filename: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ProfileParams {
+        name: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ModelParams {
+        model_name: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct NoteParams {
+        note: Note,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct UpdateNoteParams {
+        note: UpdateNote,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct UpdateNote {
+        id: usize,
+        #[serde(flatten)]
+        note: Note,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct NotesParams {
+        notes: Vec<Note>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct QueryParams {
+        query: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct NoteIdsParams {
+        notes: Vec<usize>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CardIdsParams {
+        cards: Vec<usize>,
+    }
+
+    let res = match action {
+        "version" => json!(6),
+        "deckNames" => json!(backend.deck_names()?),
+        "createDeck" => {
+            let params: DeckParams = parse_params(params)?;
+            json!(backend.create_deck(&params.deck)?)
+        }
+        "getDeckConfig" => {
+            let params: DeckParams = parse_params(params)?;
+            // anki-connect returns `false` for unknown decks
+            match backend.get_deck_config(&params.deck) {
+                Ok(config) => json!(config),
+                Err(_) => json!(false),
+            }
+        }
+        "saveDeckConfig" => {
+            let params: DeckConfigParams = parse_params(params)?;
+            json!(backend.save_deck_config(&params.config).is_ok())
+        }
+        "setDeckConfigId" => {
+            let params: DeckConfigIdParams = parse_params(params)?;
+            json!(backend
+                .set_deck_config_id(&params.decks, params.config_id)
+                .is_ok())
+        }
+        "cloneDeckConfigId" => {
+            let params: CloneDeckConfigParams = parse_params(params)?;
+            match backend.clone_deck_config_id(&params.name, params.clone_from) {
+                Ok(id) => json!(id),
+                Err(_) => json!(false),
+            }
+        }
+        "modelNames" => json!(backend.model_names()?),
+        "modelFieldNames" => {
+            let params: ModelParams = parse_params(params)?;
+            let mut names = backend.model_field_names(&[params.model_name])?;
+            json!(names.pop())
+        }
+        "modelTemplates" => {
+            let params: ModelParams = parse_params(params)?;
+            json!(backend.model_templates(&params.model_name)?)
+        }
+        "modelStyling" => {
+            let params: ModelParams = parse_params(params)?;
+            json!({ "css": backend.model_styling(&params.model_name)? })
+        }
+        "modelFieldAdd" => {
+            #[derive(Debug, Deserialize)]
+            #[serde(rename_all = "camelCase")]
+            struct Params {
+                model_name: String,
+                field_name: String,
+                #[serde(default)]
+                index: Option<usize>,
+            }
+
+            let params: Params = parse_params(params)?;
+            let index = params.index.unwrap_or(usize::MAX);
+            backend.model_field_add(&params.model_name, &params.field_name, index)?;
+            Value::Null
+        }
+        "createModel" => {
+            let params: CreateModelData = parse_params(params)?;
+            backend.create_model(&params)?;
+            json!({})
+        }
+        "addNote" => {
+            let params: NoteParams = parse_params(params)?;
+            match backend.add_notes(&[params.note])?.pop().flatten() {
+                Some(id) => json!(id),
+                None => bail!("cannot create note because it is a duplicate"),
+            }
+        }
+        "addNotes" => {
+            let params: NotesParams = parse_params(params)?;
+            json!(backend.add_notes(&params.notes)?)
+        }
+        "canAddNotes" => {
+            let params: NotesParams = parse_params(params)?;
+            json!(backend.can_add_notes(&params.notes)?)
+        }
+        "updateNote" => {
+            let params: UpdateNoteParams = parse_params(params)?;
+            backend.update_note(params.note.id, &params.note.note)?;
+            Value::Null
+        }
+        "findNotes" => {
+            let params: QueryParams = parse_params(params)?;
+            json!(backend.find_notes(&params.query)?)
+        }
+        "notesInfo" => {
+            let params: NoteIdsParams = parse_params(params)?;
+            json!(backend.notes_info(&params.notes)?)
+        }
+        "cardsInfo" => {
+            let params: CardIdsParams = parse_params(params)?;
+            json!(backend.cards_info(&params.cards)?)
+        }
+        "suspend" => {
+            let params: CardIdsParams = parse_params(params)?;
+            let suspended = backend.are_suspended(&params.cards)?;
+            backend.suspend(&params.cards)?;
+            json!(suspended.contains(&Some(false)))
+        }
+        "unsuspend" => {
+            let params: CardIdsParams = parse_params(params)?;
+            let suspended = backend.are_suspended(&params.cards)?;
+            backend.unsuspend(&params.cards)?;
+            json!(suspended.contains(&Some(true)))
+        }
+        "forgetCards" => {
+            let params: CardIdsParams = parse_params(params)?;
+            backend.forget_cards(&params.cards)?;
+            Value::Null
+        }
+        "relearnCards" => {
+            let params: CardIdsParams = parse_params(params)?;
+            backend.relearn_cards(&params.cards)?;
+            Value::Null
+        }
+        "areSuspended" => {
+            let params: CardIdsParams = parse_params(params)?;
+            json!(backend.are_suspended(&params.cards)?)
+        }
+        "storeMediaFile" => {
+            let params: MediaData = parse_params(params)?;
+            json!(backend.store_media_file(&params)?)
+        }
+        "retrieveMediaFile" => {
+            let params: FilenameParams = parse_params(params)?;
+            match backend.retrieve_media_file(&params.filename)? {
+                Some(data) => json!(data),
+                None => json!(false),
+            }
+        }
+        "getActiveProfile" => json!(backend.active_profile()?),
+        "loadProfile" => {
+            let params: ProfileParams = parse_params(params)?;
+            json!(backend.load_profile(&params.name).is_ok())
+        }
+        "sync" => {
+            backend.sync()?;
+            Value::Null
+        }
+        _ => bail!("unsupported action"),
+    };
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::api::{self, AnkiConnect, ClientOptions, CreateModelData, SingleOrMulti};
+    use crate::backend::Overlay;
+
+    /// Serve an empty collection at an unused port.
+    fn start() -> AnkiConnect {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || accept(&listener, &InMemory::default(), None));
+        AnkiConnect::new(ClientOptions {
+            endpoint: format!("http://127.0.0.1:{port}"),
+            retries: 0,
+            ..ClientOptions::default()
+        })
+    }
+
+    fn model(name: &str, fields: &[&str]) -> CreateModelData {
+        CreateModelData {
+            model_name: String::from(name),
+            in_order_fields: fields.iter().map(|field| String::from(*field)).collect(),
+            css: String::new(),
+            is_cloze: false,
+            card_templates: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn struct_response() {
+        let client = start();
+
+        assert_eq!(api::version(&client).unwrap(), 6);
+        assert_eq!(api::create_deck(&client, "Math").unwrap(), Some(2));
+        assert_eq!(api::get_deck_names(&client).unwrap().0, ["Default", "Math"]);
+        // actions without result return `null`
+        api::sync(&client).unwrap();
+    }
+
+    #[test]
+    fn error_response() {
+        let client = start();
+
+        let e = api::notes_info(&client, &[42]).unwrap_err();
+        assert!(
+            format!("{e:?}").contains("anki returned an error: note was not found: 42"),
+            "{e:?}"
+        );
+        let e = api::request::<_, Vec<String>>(&client, "guiBrowse", &serde_json::Map::new())
+            .unwrap_err();
+        assert!(format!("{e:?}").contains("unsupported action"), "{e:?}");
+    }
+
+    #[test]
+    fn multi_batch() {
+        let client = start();
+        api::create_model(&client, &model("a", &["front", "back"])).unwrap();
+        api::create_model(&client, &model("b", &["question"])).unwrap();
+
+        let names = api::get_model_field_names_multi(&client, ["b", "a", "b"]).unwrap();
+        assert_eq!(
+            names.into_iter().map(|names| names.0).collect::<Vec<_>>(),
+            [vec!["question"], vec!["front", "back"], vec!["question"]]
+        );
+
+        let e = api::get_model_field_names_multi(&client, ["a", "missing"]).unwrap_err();
+        assert!(
+            format!("{e:?}").contains("model was not found: missing"),
+            "{e:?}"
+        );
+    }
+
+    #[test]
+    fn overlay_ids() {
+        let client = start();
+        api::create_model(&client, &model("a", &["front"])).unwrap();
+        let note = |front: &str| Note {
+            deck_name: String::from("Default"),
+            model_name: String::from("a"),
+            fields: HashMap::from([(String::from("front"), String::from(front))]),
+            tags: Vec::new(),
+            audio: SingleOrMulti::default(),
+            video: SingleOrMulti::default(),
+            picture: SingleOrMulti::default(),
+        };
+        let inner = api::add_note(&client, &note("inner")).unwrap().unwrap();
+
+        // the stub counts its ids from zero, like the overlay would without the timestamp
+        let overlay = Overlay::new(client).unwrap();
+        let local = overlay.add_notes(&[note("local")]).unwrap()[0].unwrap();
+        assert_ne!(local, inner);
+        let mut ids = overlay.find_notes("*").unwrap();
+        ids.sort_unstable();
+        assert_eq!(ids, [inner, local]);
+        let fronts = overlay
+            .notes_info(&ids)
+            .unwrap()
+            .into_iter()
+            .map(|info| info.fields["front"].value.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(fronts, ["inner", "local"]);
+    }
+}
@@ -0,0 +1,104 @@
+//! Tags which are managed by anki-typst.
+//!
+//! All of them are in the namespace [`NAMESPACE`], e.g. `anki-typst::id::Pythagoras`.
+
+use crate::source::Location;
+
+/// Namespace of all tags managed by anki-typst.
+pub const NAMESPACE: &str = "anki-typst::";
+
+const ID: &str = "anki-typst::id::";
+const HASH: &str = "anki-typst::hash::";
+const SOURCE: &str = "anki-typst::source::";
+const REVISION: &str = "anki-typst::revision::";
+const DATE: &str = "anki-typst::date::";
+/// Tag of all notes written by anki-typst.
+///
+/// It isn't in [`NAMESPACE`], so `fetch_scope = "generated"` also finds the notes of older versions.
+pub const GENERATED: &str = "generated";
+/// Tag of notes whose cards were suspended by anki-typst because of a flag in typst.
+pub const SUSPENDED: &str = "anki-typst::suspended";
+
+/// Whether the tag is managed by anki-typst.
+pub fn is_managed(tag: &str) -> bool {
+    tag.starts_with(NAMESPACE)
+}
+
+/// Whether the tag is added by anki-typst, i.e. it is managed or [`GENERATED`].
+pub fn is_generated(tag: &str) -> bool {
+    is_managed(tag) || tag == GENERATED
+}
+
+/// Tags can't contain spaces, so they are percent encoded.
+fn encode(value: &str) -> String {
+    value.replace('%', "%25").replace(' ', "%20")
+}
+
+fn decode(value: &str) -> String {
+    value.replace("%20", " ").replace("%25", "%")
+}
+
+fn find<'a>(tags: &'a [String], prefix: &str) -> Option<&'a str> {
+    tags.iter().find_map(|tag| tag.strip_prefix(prefix))
+}
+
+/// Tag which stores the typst id of a note.
+pub fn id(id: &str) -> String {
+    format!("{ID}{}", encode(id))
+}
+
+/// Get the typst id from the tags of a note.
+pub fn find_id(tags: &[String]) -> Option<String> {
+    find(tags, ID).map(decode)
+}
+
+/// Tag which stores the hash of the typst content a note was created from.
+pub fn hash(hash: u64) -> String {
+    format!("{HASH}{hash:016x}")
+}
+
+/// Get the content hash from the tags of a note.
+pub fn find_hash(tags: &[String]) -> Option<u64> {
+    find(tags, HASH).and_then(|hash| u64::from_str_radix(hash, 16).ok())
+}
+
+/// Tag which stores the location of the note in the typst sources.
+pub fn source(location: &Location) -> String {
+    format!("{SOURCE}{}", encode(&location.to_string()))
+}
+
+/// Get the location in the typst sources from the tags of a note.
+pub fn find_source(tags: &[String]) -> Option<Location> {
+    find(tags, SOURCE).and_then(|location| Location::parse(&decode(location)))
+}
+
+/// Tag which stores the revision of the typst item a note was created from.
+pub fn revision(revision: &str) -> String {
+    format!("{REVISION}{}", encode(revision))
+}
+
+/// Tag which stores the date a note was written by anki-typst.
+pub fn date(date: &str) -> String {
+    format!("{DATE}{date}")
+}
+
+/// Get the revision from the tags of a note.
+pub fn find_revision(tags: &[String]) -> Option<String> {
+    find(tags, REVISION).map(decode)
+}
+
+/// `tags` with the tags for the suspension and the revision replaced by the given ones.
+pub fn with_state(tags: &[String], suspended: bool, revision: Option<&str>) -> Vec<String> {
+    let mut tags = tags
+        .iter()
+        .filter(|tag| *tag != SUSPENDED && !tag.starts_with(REVISION))
+        .cloned()
+        .collect::<Vec<_>>();
+    if suspended {
+        tags.push(String::from(SUSPENDED));
+    }
+    if let Some(revision) = revision {
+        tags.push(self::revision(revision));
+    }
+    tags
+}
@@ -0,0 +1,151 @@
+//! Minimal templates for the html of fields.
+//!
+//! `{{name}}` is replaced by the html escaped value of the variable `name`,
+//! `{{{name}}}` by the unescaped value.
+
+use color_eyre::eyre::{bail, eyre};
+use color_eyre::Result;
+
+/// Variables which can be used in templates for images.
+pub const IMAGE_VARIABLES: [&str; 10] = [
+    "filename",
+    "page",
+    "page_number",
+    "theme",
+    "class",
+    "alt",
+    "id",
+    "deck",
+    "model",
+    "field",
+];
+
+/// Escape `value` so it can be used in html text and attributes.
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Variable { name: &'a str, escape: bool },
+}
+
+fn parse(template: &str) -> Result<Vec<Part<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        parts.push(Part::Text(&rest[..start]));
+        rest = &rest[start..];
+        let (open, close, escape) = if rest.starts_with("{{{") {
+            ("{{{", "}}}", false)
+        } else {
+            ("{{", "}}", true)
+        };
+        let Some(end) = rest.find(close) else {
+            bail!("unclosed `{}` in template: {}", open, template);
+        };
+        let name = rest[open.len()..end].trim();
+        if name.is_empty() {
+            bail!("empty variable in template: {}", template);
+        }
+        parts.push(Part::Variable { name, escape });
+        rest = &rest[end + close.len()..];
+    }
+    parts.push(Part::Text(rest));
+    Ok(parts)
+}
+
+/// Check that `template` is valid and only uses `variables`.
+pub fn validate(template: &str, variables: &[&str]) -> Result<()> {
+    for part in parse(template)? {
+        if let Part::Variable { name, .. } = part {
+            if !variables.contains(&name) {
+                bail!(
+                    "unknown variable `{}` in template, expected one of {}",
+                    name,
+                    variables.join(", ")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Render `template` with the given variables.
+pub fn render(template: &str, variables: &[(&str, &str)]) -> Result<String> {
+    let mut res = String::with_capacity(template.len());
+    for part in parse(template)? {
+        match part {
+            Part::Text(text) => res.push_str(text),
+            Part::Variable { name, escape } => {
+                let (_, value) = variables
+                    .iter()
+                    .find(|(var, _)| *var == name)
+                    .ok_or_else(|| eyre!("unknown variable `{}` in template", name))?;
+                if escape {
+                    res.push_str(&escape_html(value));
+                } else {
+                    res.push_str(value);
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            escape_html("<b class=\"x\">Tom & Jerry's</b>"),
+            "&lt;b class=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/b&gt;"
+        );
+        assert_eq!(escape_html("a² + b²"), "a² + b²");
+    }
+
+    #[test]
+    fn escaped_and_raw_variables() {
+        let variables = [("alt", "a < b"), ("filename", "a&b.png")];
+        assert_eq!(
+            render("<img src=\"{{{filename}}}\" alt=\"{{ alt }}\">", &variables).unwrap(),
+            "<img src=\"a&b.png\" alt=\"a &lt; b\">"
+        );
+        assert_eq!(render("{{filename}}", &variables).unwrap(), "a&amp;b.png");
+        assert_eq!(render("no variables", &variables).unwrap(), "no variables");
+        let e = render("{{page}}", &variables).unwrap_err();
+        assert!(
+            format!("{e:?}").contains("unknown variable `page`"),
+            "{e:?}"
+        );
+    }
+
+    #[test]
+    fn invalid_templates() {
+        let e = validate("<img src=\"{{filename\">", &IMAGE_VARIABLES).unwrap_err();
+        assert!(format!("{e:?}").contains("unclosed `{{`"), "{e:?}");
+        let e = validate("{{{alt}}", &IMAGE_VARIABLES).unwrap_err();
+        assert!(format!("{e:?}").contains("unclosed `{{{`"), "{e:?}");
+        let e = validate("{{ }}", &IMAGE_VARIABLES).unwrap_err();
+        assert!(format!("{e:?}").contains("empty variable"), "{e:?}");
+        let e = validate("{{filename}} {{size}}", &IMAGE_VARIABLES).unwrap_err();
+        assert!(
+            format!("{e:?}").contains("unknown variable `size`"),
+            "{e:?}"
+        );
+        validate("{{filename}} {{{alt}}} {{ page_number }}", &IMAGE_VARIABLES).unwrap();
+    }
+}
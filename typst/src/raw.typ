@@ -1,5 +1,5 @@
-#import "config.typ": anki_config
-#import "utils.typ": assert_ty, to_plain, get_label_page, to_string
+#import "config.typ": anki_config, get_val_from_sys
+#import "utils.typ": assert_ty, to_plain, get_label_page, to_string, get_heading_path, has_equation, to_mathjax, to_text
 
 /// Same as `anki_export` but takes the config.
 ///
@@ -11,6 +11,12 @@
 /// - deck (str): Name of the card deck. Anki nests decks with `::`, so you can try `Deck::Subdeck`.
 /// - model (str): Name of the card model.
 /// - number (int, str, none): The number of the card. Not really special but passed differently to the command line interface.
+/// - media (array): Audio, video and picture files to attach to the card, see `anki_export`.
+/// - draft (bool): The card is not ready yet. It is exported but its cards are suspended.
+/// - retired (bool): The card is not relevant anymore. It is exported but its cards are suspended.
+/// - revision (none, int, str): Change it to mark a major edit, see `on_revision`.
+/// - on_revision (none, str): What happens to the cards when `revision` changes: `"keep"`, `"reset"` or `"relearn"`.
+///     `none` uses the setting of the command line interface.
 /// - ..fields (arguments): Additional fields for the anki card.
 #let _anki_export_with_config(
   config,
@@ -19,11 +25,45 @@
   deck: none,
   model: none,
   number: none,
+  media: (),
+  draft: false,
+  retired: false,
+  revision: none,
+  on_revision: none,
   ..fields,
 ) = {
   for tag in tags {
     let _ = assert_ty("tag", tag, str)
   }
+  let _ = assert_ty("media", media, array)
+  let _ = assert_ty("draft", draft, bool)
+  let _ = assert_ty("retired", retired, bool)
+  if on_revision not in (none, "keep", "reset", "relearn") {
+    panic("on_revision must be none, \"keep\", \"reset\" or \"relearn\" but was " + repr(on_revision))
+  }
+  if type(revision) == content {
+    panic("revision may not be content but was " + repr(revision))
+  }
+  let revision = if revision == none { none } else { str(revision) }
+  let media = media.map(entry => {
+    let entry = if type(entry) == str {
+      (path: entry)
+    } else {
+      assert_ty("media entry", entry, dictionary)
+    }
+    if "path" not in entry {
+      panic("media entry must have a path: " + repr(entry))
+    }
+    let fields = entry.at("fields", default: entry.at("field", default: ()))
+    if type(fields) == str {
+      fields = (fields,)
+    }
+    (
+      path: assert_ty("media path", entry.path, str),
+      fields: fields,
+      kind: entry.at("kind", default: none),
+    )
+  })
   if fields.pos().len() > 0 {
     panic("expected only named arguments", fields.pos())
   }
@@ -47,6 +87,12 @@
         model: model,
         fields: (:),
         tags: tags,
+        headings: get_heading_path(loc),
+        media: media,
+        draft: draft,
+        retired: retired,
+        revision: revision,
+        on_revision: on_revision,
       )
       if config.date != none {
         meta.fields.insert("date", config.date)
@@ -54,8 +100,22 @@
       if number != none {
         meta.fields.insert("number", number)
       }
+      // set by the command line interface
+      let plain_math = get_val_from_sys(
+        "plain_math",
+        default: "text",
+        options: (("text", "text"), ("mathjax", "mathjax")),
+      )
       for (name, val) in fields.pairs() {
         let plain = to_plain(val)
+        let mathjax = none
+        if plain_math == "mathjax" and plain != none and has_equation(val) {
+          mathjax = to_mathjax(val)
+          if mathjax == none {
+            // render math which can't be converted as image
+            plain = none
+          }
+        }
         let spacer = "<<anki>>"
         let start_id = deck + id + name + "start"
         let end_id = deck + id + name + "end"
@@ -83,6 +143,7 @@
             name,
             (
               content: to_string(val),
+              text: to_text(val).replace(regex("\\s+"), " ").trim(),
               page_start: page_start,
               page_end: page_end,
             ),
@@ -95,9 +156,11 @@
           ]
           meta.fields.insert(
             name,
-            (
-              plain: plain,
-            ),
+            if mathjax == none {
+              (plain: plain)
+            } else {
+              (plain: plain, mathjax: mathjax)
+            },
           )
         }
       }
@@ -132,6 +195,15 @@
 /// - deck (str): Name of the card deck. Anki nests decks with `::`, so you can try `Deck::Subdeck`.
 /// - model (str): Name of the card model.
 /// - number (int, str, none): The number of the card. Not really special but passed differently to the command line interface.
+/// - media (array): Audio, video and picture files to attach to the card.
+///     Each entry is a path or a dictionary `(path: .., field: .., kind: ..)`.
+///     Paths are relative to the typst root. The file is appended to `field` (or all `fields`).
+///     `kind` is one of `"audio"`, `"video"` and `"picture"` and guessed from the file extension by default.
+/// - draft (bool): The card is not ready yet. It is exported but its cards are suspended.
+/// - retired (bool): The card is not relevant anymore. It is exported but its cards are suspended.
+/// - revision (none, int, str): Change it to mark a major edit, see `on_revision`.
+/// - on_revision (none, str): What happens to the cards when `revision` changes: `"keep"`, `"reset"` or `"relearn"`.
+///     `none` uses the setting of the command line interface.
 /// - ..fields (arguments): Additional fields for the anki card.
 #let anki_export(
   id: none,
@@ -139,9 +211,14 @@
   deck: none,
   model: none,
   number: none,
+  media: (),
+  draft: false,
+  retired: false,
+  revision: none,
+  on_revision: none,
   ..fields,
 ) = {
   anki_config.display(config => {
-    _anki_export_with_config(config, id: id, tags: tags, deck: deck, model: model, number: number, ..fields)
+    _anki_export_with_config(config, id: id, tags: tags, deck: deck, model: model, number: number, media: media, draft: draft, retired: retired, revision: revision, on_revision: on_revision, ..fields)
   })
 }
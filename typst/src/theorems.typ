@@ -167,6 +167,12 @@
 /// - numbering (str, function, none): The pattern for the primary number.
 /// - secondary (none, auto, true, function, array): The secondary number of the card.
 /// - secondary_numbering (str, function, none): The pattern for the secondary number.
+/// - media (array): Audio, video and picture files to attach to the card, see `anki_export`.
+/// - draft (bool): The card is not ready yet. It is exported but its cards are suspended.
+/// - retired (bool): The card is not relevant anymore. It is exported but its cards are suspended.
+/// - revision (none, int, str): Change it to mark a major edit, see `on_revision`.
+/// - on_revision (none, str): What happens to the cards when `revision` changes: `"keep"`, `"reset"` or `"relearn"`.
+///     `none` uses the setting of the command line interface.
 /// - ..fields (arguments): Additional fields for the anki card.
 #let _anki_thm_with_loc(
   loc,
@@ -178,6 +184,11 @@
   number: auto,
   secondary: none,
   secondary_numbering: "a",
+  media: (),
+  draft: false,
+  retired: false,
+  revision: none,
+  on_revision: none,
   ..fields,
 ) = {
   let _ = assert_ty("tags", tags, array)
@@ -221,6 +232,11 @@
       deck: deck,
       model: model,
       number: number,
+      media: media,
+      draft: draft,
+      retired: retired,
+      revision: revision,
+      on_revision: on_revision,
       ..fields,
     ),
   )
@@ -252,6 +268,12 @@
 /// - numbering (str, function, none): The pattern for the primary number.
 /// - secondary (none, auto, true, function, array): The secondary number of the card.
 /// - secondary_numbering (str, function, none): The pattern for the secondary number.
+/// - media (array): Audio, video and picture files to attach to the card, see `anki_export`.
+/// - draft (bool): The card is not ready yet. It is exported but its cards are suspended.
+/// - retired (bool): The card is not relevant anymore. It is exported but its cards are suspended.
+/// - revision (none, int, str): Change it to mark a major edit, see `on_revision`.
+/// - on_revision (none, str): What happens to the cards when `revision` changes: `"keep"`, `"reset"` or `"relearn"`.
+///     `none` uses the setting of the command line interface.
 /// - ..fields (arguments): Additional fields for the anki card.
 #let anki_thm(
   id,
@@ -262,6 +284,11 @@
   number: auto,
   secondary: none,
   secondary_numbering: "a",
+  media: (),
+  draft: false,
+  retired: false,
+  revision: none,
+  on_revision: none,
   ..fields,
 ) = {
   locate(loc => {
@@ -275,6 +302,11 @@
       number: number,
       secondary: secondary,
       secondary_numbering: secondary_numbering,
+      media: media,
+      draft: draft,
+      retired: retired,
+      revision: revision,
+      on_revision: on_revision,
       ..fields
     )
   })
@@ -475,6 +507,12 @@
   /// - clear_tags (bool): Remove `initial_tags` and use only `tags`.
   /// - number (auto, function, array): The primary number of the card.
   /// - secondary (none, auto, true, function, array): The secondary number of the card.
+  /// - media (array): Audio, video and picture files to attach to the card, see `anki_export`.
+  /// - draft (bool): The card is not ready yet. It is exported but its cards are suspended.
+  /// - retired (bool): The card is not relevant anymore. It is exported but its cards are suspended.
+  /// - revision (none, int, str): Change it to mark a major edit, see `on_revision`.
+  /// - on_revision (none, str): What happens to the cards when `revision` changes: `"keep"`, `"reset"` or `"relearn"`.
+  ///     `none` uses the setting of the command line interface.
   /// - ..maybe_proof (none, content): The proof of the card if specified.
   let inner(
     front,
@@ -485,6 +523,11 @@
     clear_tags: false,
     number: auto,
     secondary: none,
+    media: (),
+    draft: false,
+    retired: false,
+    revision: none,
+    on_revision: none,
     ..maybe_proof,
   ) = {
     let proof = (() => {
@@ -577,6 +620,11 @@
             secondary: secondary,
             secondary_numbering: secondary_numbering,
             tags: tags,
+            media: media,
+            draft: draft,
+            retired: retired,
+            revision: revision,
+            on_revision: on_revision,
             ..fields,
           )
         })
@@ -132,3 +132,200 @@
   elems.at(0).location().page()
 }
 
+
+/// Get the bodies of all headings which contain the location, from the outermost to the innermost.
+///
+/// Headings which aren't plain text are converted with `to_string`.
+/// - loc (location): Current location
+/// -> array
+#let get_heading_path(loc) = {
+  let path = ()
+  for elem in query(selector(heading).before(loc)) {
+    let body = to_plain(elem.body)
+    if body == none {
+      body = to_string(elem.body)
+    }
+    path = path.slice(0, calc.min(path.len(), elem.level - 1))
+    path.push(body)
+  }
+  path
+}
+
+/// LaTeX commands for symbols which MathJax can't read directly.
+#let _latex_symbols = (
+  "α": "\\alpha", "β": "\\beta", "γ": "\\gamma", "δ": "\\delta", "ε": "\\varepsilon", "ϵ": "\\epsilon",
+  "ζ": "\\zeta", "η": "\\eta", "θ": "\\theta", "ϑ": "\\vartheta", "ι": "\\iota", "κ": "\\kappa",
+  "λ": "\\lambda", "μ": "\\mu", "ν": "\\nu", "ξ": "\\xi", "π": "\\pi", "ρ": "\\rho", "σ": "\\sigma",
+  "τ": "\\tau", "υ": "\\upsilon", "φ": "\\varphi", "ϕ": "\\phi", "χ": "\\chi", "ψ": "\\psi", "ω": "\\omega",
+  "Γ": "\\Gamma", "Δ": "\\Delta", "Θ": "\\Theta", "Λ": "\\Lambda", "Ξ": "\\Xi", "Π": "\\Pi",
+  "Σ": "\\Sigma", "Φ": "\\Phi", "Ψ": "\\Psi", "Ω": "\\Omega",
+  "−": "-", "·": "\\cdot", "⋅": "\\cdot", "×": "\\times", "÷": "\\div", "±": "\\pm", "∓": "\\mp",
+  "≤": "\\leq", "≥": "\\geq", "≠": "\\neq", "≈": "\\approx", "≡": "\\equiv", "∼": "\\sim",
+  "→": "\\to", "←": "\\leftarrow", "↦": "\\mapsto", "⇒": "\\Rightarrow", "⇐": "\\Leftarrow", "⇔": "\\Leftrightarrow",
+  "∞": "\\infty", "∂": "\\partial", "∇": "\\nabla", "∑": "\\sum", "∏": "\\prod", "∫": "\\int",
+  "∈": "\\in", "∉": "\\notin", "⊂": "\\subset", "⊆": "\\subseteq", "⊃": "\\supset", "⊇": "\\supseteq",
+  "∪": "\\cup", "∩": "\\cap", "∅": "\\emptyset", "∀": "\\forall", "∃": "\\exists", "¬": "\\neg",
+  "∧": "\\wedge", "∨": "\\vee", "∘": "\\circ", "…": "\\ldots", "⋯": "\\cdots", "′": "'",
+  "ℕ": "\\mathbb{N}", "ℤ": "\\mathbb{Z}", "ℚ": "\\mathbb{Q}", "ℝ": "\\mathbb{R}", "ℂ": "\\mathbb{C}",
+  "{": "\\{", "}": "\\}", "%": "\\%", "#": "\\#", "&": "\\&", "_": "\\_",
+)
+
+/// Determine whether content contains an equation.
+///
+/// - c (content): Content to check.
+/// -> bool
+#let has_equation(c) = {
+  if type(c) != content {
+    false
+  } else if c.func() == math.equation {
+    true
+  } else if c.has("children") {
+    c.children.any(has_equation)
+  } else if c.has("body") {
+    has_equation(c.body)
+  } else {
+    false
+  }
+}
+
+/// Convert math content to LaTeX.
+///
+/// Only a subset of typst math is supported.
+/// - c (content, str): Math content to convert.
+/// -> str, none
+#let to_latex(c) = {
+  let wrap(c) = {
+    let res = to_latex(c)
+    if res == none { none } else { "{" + res + "}" }
+  }
+
+  if c == none {
+    ""
+  } else if type(c) == str {
+    if c.clusters().len() == 1 {
+      _latex_symbols.at(c, default: c)
+    } else if c.match(regex("^[0-9.,]+$")) != none {
+      c
+    } else {
+      "\\text{" + c + "}"
+    }
+  } else if type(c) != content {
+    none
+  } else if c.func() == math.equation {
+    to_latex(c.body)
+  } else if c.has("children") {
+    let parts = c.children.map(to_latex)
+    if parts.contains(none) { none } else { parts.join("") }
+  } else if c.func() == text {
+    to_latex(c.text)
+  } else if c.func() == [ ].func() or c.func() == h {
+    " "
+  } else if c.func() == math.attach {
+    let base = wrap(c.base)
+    let parts = (base,)
+    if c.has("tr") and c.tr.func() == math.primes {
+      parts.push(to_latex(c.tr))
+    }
+    for (key, op) in (("b", "_"), ("t", "^")) {
+      if c.has(key) {
+        let val = wrap(c.at(key))
+        parts.push(if val == none { none } else { op + val })
+      }
+    }
+    let unsupported = ("tl", "bl", "br").any(key => c.has(key)) or (c.has("tr") and c.tr.func() != math.primes)
+    if parts.contains(none) or unsupported {
+      none
+    } else {
+      parts.join("")
+    }
+  } else if c.func() == math.frac {
+    let (num, denom) = (wrap(c.num), wrap(c.denom))
+    if num == none or denom == none { none } else { "\\frac" + num + denom }
+  } else if c.func() == math.root {
+    let radicand = wrap(c.radicand)
+    let index = if c.has("index") and c.index != none { to_latex(c.index) } else { "" }
+    if radicand == none or index == none {
+      none
+    } else if index == "" {
+      "\\sqrt" + radicand
+    } else {
+      "\\sqrt[" + index + "]" + radicand
+    }
+  } else if c.func() == math.lr {
+    to_latex(c.body)
+  } else if c.func() == math.primes {
+    "'" * c.count
+  } else if c.func() == math.op {
+    let op = to_string(c.text)
+    if op == none { none } else { "\\operatorname{" + op + "}" }
+  } else {
+    none
+  }
+}
+
+/// Convert content with equations to text with MathJax delimiters.
+///
+/// Returns `none` if some of the math can't be converted.
+/// - c (content, str): Content to convert.
+/// -> str, none
+#let to_mathjax(c) = {
+  if type(c) == str {
+    c
+  } else if type(c) != content {
+    none
+  } else if c.func() == math.equation {
+    let latex = to_latex(c.body)
+    if latex == none {
+      none
+    } else if c.block {
+      "\\[" + latex.trim() + "\\]"
+    } else {
+      "\\(" + latex.trim() + "\\)"
+    }
+  } else if c.has("children") {
+    let parts = c.children.map(to_mathjax)
+    if parts.contains(none) { none } else { parts.join("") }
+  } else {
+    to_plain(c)
+  }
+}
+
+/// Convert content to searchable text.
+///
+/// Unlike `to_string` this keeps the text of equations, e.g. `a^2 + b^2`.
+/// - c (content, str): Content to convert.
+/// -> str
+#let to_text(c) = {
+  if c == none {
+    ""
+  } else if type(c) == str {
+    c
+  } else if type(c) != content {
+    str(c)
+  } else if c.func() == math.attach {
+    let res = to_text(c.base)
+    if c.has("b") {
+      res += "_" + to_text(c.b)
+    }
+    if c.has("t") {
+      res += "^" + to_text(c.t)
+    }
+    res
+  } else if c.func() == math.frac {
+    to_text(c.num) + "/" + to_text(c.denom)
+  } else if c.func() == math.root {
+    "√" + to_text(c.radicand)
+  } else if c.func() == math.op {
+    to_text(c.text)
+  } else if c.has("text") {
+    to_text(c.text)
+  } else if c.has("children") {
+    c.children.map(to_text).join("")
+  } else if c.has("body") {
+    to_text(c.body)
+  } else if c.func() == [ ].func() or c.func() == linebreak or c.func() == parbreak {
+    " "
+  } else {
+    ""
+  }
+}
@@ -56,6 +56,10 @@
       },
       "tags": [
         "proof"
+      ],
+      "headings": [
+        "Heading",
+        "Subheading"
       ]
     },
     "label": "<anki-export>"
@@ -72,12 +76,17 @@
         },
         "back": {
           "content": " Did you know?  +  =  ",
+          "text": "Did you know? a^2 + b^2 = c^2",
           "page_start": 2,
           "page_end": 2
         }
       },
       "tags": [
         "example"
+      ],
+      "headings": [
+        "Heading",
+        "Subheading"
       ]
     },
     "label": "<anki-export>"